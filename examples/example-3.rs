@@ -4,7 +4,7 @@ extern crate typer;
 use std::fs::{File};
 use std::path::{PathBuf, Path};
 use std::io::Read;
-use typer::{TextRenderer, Typer, ImgBufferRef, ImgBufferTrait};
+use typer::{TextRenderer, Typer, ImgBufferRef};
 use image::*;
 
 fn main() {
@@ -20,26 +20,13 @@ fn main() {
 	let fonts = TextRenderer::load_fonts(fonts);
 	let mut typer = Typer::new();
 	let blocks = typer.parse(&data);
-	let mut layout = TextRenderer::format(blocks, 1.0, &fonts);
-	
-	layout.calk_view();
-	let borders = 20;
-	let t_box = (layout.x.floor() as isize, layout.y.floor() as isize, layout.width.ceil() as isize, layout.height.ceil() as isize);
-	layout.x = 0.0;
-	layout.y = 0.0;
-	
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+
 	let width = im.width();
 	let height = im.height();
 	let mut buf = im.into_raw();
 	{
 		let mut buffer = ImgBufferRef::new(width as usize, height as usize, &mut buf);
-
-		for x in t_box.0-borders..t_box.2+borders {
-			for y in t_box.1-borders..t_box.3+borders {
-				buffer.blend_pixel(x.max(0) as usize, y.max(0) as usize, &[0,0,0,255], 0.8);
-			}			
-		}
-
 		TextRenderer::render(&layout, &mut buffer);
 	}
 
@@ -1,7 +1,13 @@
 extern crate rusttype;
 
-use img_buffer::{ImgBuffer, ColorRGBA};
-use self::rusttype::{ScaledGlyph};
+use std::cmp::Ordering;
+use std::fmt;
+use std::rc::Rc;
+use img_buffer::{ImgBuffer, ColorRGBA, BlendMode};
+use opentype::FontFeature;
+use exclusion::Exclusion;
+use table::Table;
+use self::rusttype::{ScaledGlyph, Point, Rect};
 
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -12,23 +18,785 @@ pub enum TextAlignHorizontal {
 	Justify,
 }
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TextAlignVertical {
+	Top,
+	Middle,
+	Bottom,
+	Baseline,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TabAlign {
+	Left,
+	Right,
+	Center,
+	Decimal,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DecorationStyle {
+	Solid,
+	Double,
+	Dotted,
+	Wavy,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TextTransform {
+	None,
+	Uppercase,
+	Lowercase,
+	Capitalize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BaselineShift {
+	Baseline,
+	Super,
+	Sub,
+	Length(Length),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PaintArea {
+	Glyph,
+	Span,
+	Block,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ImageAlign {
+	Baseline,
+	Middle,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LineJoin {
+	Round,
+	Miter,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LineBreaking {
+	Greedy,
+	Optimal,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Overflow {
+	Visible,
+	Clip,
+	Ellipsis,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BlockFit {
+	None,
+	Shrink,
+	Grow,
+	Both,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+	Px(f32),
+	Em(f32),
+}
+
+
+impl Length {
+
+	// 2, 2px or 0.1em
+	pub fn parse(val: &str) -> Option<Self> {
+		let val = val.trim();
+		if let Some(v) = val.strip_suffix("em") {
+			return v.trim().parse::<f32>().ok().map(Length::Em);
+		}
+		val.trim_end_matches("px").trim().parse::<f32>().ok().map(Length::Px)
+	}
+
+	pub fn to_px(self, font_size: f32) -> f32 {
+		match self {
+			Length::Px(v) => v,
+			Length::Em(v) => v * font_size,
+		}
+	}
+
+	// em follows the font size by itself
+	pub fn scale(self, factor: f32) -> Self {
+		match self {
+			Length::Px(v) => Length::Px(v * factor),
+			Length::Em(v) => Length::Em(v),
+		}
+	}
+}
+
+impl BaselineShift {
+
+	// super and sub shrink the text to 0.7, the shifts are of the size before it
+	pub fn to_px(self, font_size: f32) -> f32 {
+		match self {
+			BaselineShift::Baseline => 0.0,
+			BaselineShift::Super => font_size / 0.7 * 0.33,
+			BaselineShift::Sub => - font_size / 0.7 * 0.2,
+			BaselineShift::Length(length) => length.to_px(font_size),
+		}
+	}
+}
+
+impl TextTransform {
+
+	// prev is the symbol before the text, to find the start of the words for capitalize
+	pub fn apply(self, text: &str, lang: Option<&str>, prev: Option<char>) -> String {
+		// turkish and azerbaijani have the dotted İ and the dotless ı
+		let is_turkic = lang.is_some_and(|lang| {
+			let lang = lang.to_lowercase();
+			lang == "tr" || lang == "az" || lang.starts_with("tr-") || lang.starts_with("az-")
+		});
+		let to_upper = |text: &str| -> String {
+			if is_turkic {
+				text.replace('i', "İ").to_uppercase()
+			} else {
+				text.to_uppercase()
+			}
+		};
+
+		match self {
+			TextTransform::None 		=> text.to_string(),
+			TextTransform::Uppercase 	=> to_upper(text),
+			TextTransform::Lowercase 	=> {
+				if is_turkic {
+					text.replace('I', "ı").replace('İ', "i").to_lowercase()
+				} else {
+					text.to_lowercase()
+				}
+			}
+			TextTransform::Capitalize 	=> {
+				let mut res = String::with_capacity(text.len());
+				let mut prev = prev;
+				for symbol in text.chars() {
+					let is_word_start = prev.is_none_or(|c| !c.is_alphanumeric() && c != '\'' && c != '’' && c != '\u{ad}');
+					if is_word_start && symbol.is_alphabetic() {
+						// titlecase, ß => Ss
+						let upper = to_upper(symbol.encode_utf8(&mut [0; 4]));
+						let mut upper = upper.chars();
+						res.extend(upper.next());
+						res.push_str(&upper.as_str().to_lowercase());
+					} else {
+						res.push(symbol);
+					}
+					prev = Some(symbol);
+				}
+				res
+			}
+		}
+	}
+}
+
+// #rrggbb or #rrggbbaa
+pub fn parse_color(val: &str) -> Option<ColorRGBA> {
+	if !val.starts_with('#') || (val.len() != 7 && val.len() != 9) {return None};
+	let channel = |i: usize| val.get(i..i+2).and_then(|e| u8::from_str_radix(e, 16).ok());
+	Some([channel(1)?, channel(3)?, channel(5)?, if val.len() == 9 {channel(7)?} else {255}])
+}
+
+
 #[derive(Debug)]
 pub enum FormatChunks {
-	Chunk(FormatChunk),
+	Chunk(Box<FormatChunk>),
 	String(String),
 }
 
 
-#[derive(Debug)]
+// position is relative to the left of the column
+#[derive(Debug, Clone, Copy)]
+pub struct TabStop {
+	pub position: f32,
+	pub align: TabAlign,
+	pub leader: Option<char>,
+}
+
+
+impl TabStop {
+
+	// position[:align[:leader]], like 300:right:.
+	pub fn parse(val: &str) -> Option<Self> {
+		let mut parts = val.split(':');
+		let position = parts.next()?.trim().parse::<f32>().ok()?;
+		let align = match parts.next().map(|e| e.trim()) {
+			None|Some("")|Some("left")|Some("LEFT") 	=> TabAlign::Left,
+			Some("right")|Some("RIGHT") 				=> TabAlign::Right,
+			Some("center")|Some("CENTER") 				=> TabAlign::Center,
+			Some("decimal")|Some("DECIMAL") 			=> TabAlign::Decimal,
+			_ => return None,
+		};
+		let leader = parts.next().and_then(|e| e.chars().next());
+
+		Some(Self {
+			position,
+			align,
+			leader,
+		})
+	}
+}
+
+
+// properties of a <p> or <li>, text-indent is negative for a hanging indent
+#[derive(Debug, Clone, Default)]
+pub struct Paragraph {
+	pub margin_left: f32,
+	pub text_indent: f32,
+	pub spacing_before: f32,
+	pub spacing_after: f32,
+	pub text_align: Option<TextAlignHorizontal>,
+	// drawn in front of the first line
+	pub marker: Option<String>,
+}
+
+
+impl Paragraph {
+
+	pub fn new() -> Self {
+		Self {
+			margin_left: 0.0,
+			text_indent: 0.0,
+			spacing_before: 0.0,
+			spacing_after: 0.0,
+			text_align: None,
+			marker: None,
+		}
+	}
+
+	pub fn has_attribute(key: &str) -> bool {
+		matches!(key, "text-indent"|"paragraph-spacing"|"paragraph-spacing-before"|"paragraph-spacing-after"|"text-align")
+	}
+
+	pub fn set_attribute(&mut self, key: &str, val: &str) {
+		match key {
+			"text-indent" 	=> { self.text_indent = val.parse::<f32>().unwrap() }
+			"paragraph-spacing" 	=> {
+				let values: Vec<f32> = val
+					.split_whitespace()
+					.map(|e| e.parse::<f32>().unwrap().abs())
+					.collect();
+				match values[..] {
+					[spacing] => {
+						self.spacing_before = spacing;
+						self.spacing_after = spacing;
+					}
+					[before, after] => {
+						self.spacing_before = before;
+						self.spacing_after = after;
+					}
+					_ => { println!("wrong value '{}' for attribute: '{}' => before [after]", val, key); }
+				}
+			}
+			"paragraph-spacing-before" 	=> { self.spacing_before = val.parse::<f32>().unwrap().abs() }
+			"paragraph-spacing-after" 	=> { self.spacing_after = val.parse::<f32>().unwrap().abs() }
+			"text-align" 	=> {
+				match val {
+					"left"|"LEFT" 		=> { self.text_align = Some(TextAlignHorizontal::Left) }
+					"right"|"RIGHT" 	=> { self.text_align = Some(TextAlignHorizontal::Right) }
+					"center"|"CENTER" 	=> { self.text_align = Some(TextAlignHorizontal::Center) }
+					"justify"|"JUSTIFY" => { self.text_align = Some(TextAlignHorizontal::Justify) }
+					_ => { println!("unknown value '{}' for attribute: '{}'", val, key); }
+				}
+			}
+			_ => {
+				println!("unknown attribute: '{}'", key);
+			}
+		}
+	}
+}
+
+
+// thickness and color are taken from the font and the text if not set
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextDecoration {
+	pub underline: bool,
+	pub overline: bool,
+	pub line_through: bool,
+	pub style: DecorationStyle,
+	pub thickness: Option<f32>,
+	pub color: Option<ColorRGBA>,
+	pub skip_ink: bool,
+}
+
+
+impl Default for TextDecoration {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+
+impl TextDecoration {
+
+	pub fn new() -> Self {
+		Self {
+			underline: false,
+			overline: false,
+			line_through: false,
+			style: DecorationStyle::Solid,
+			thickness: None,
+			color: None,
+			skip_ink: true,
+		}
+	}
+
+	pub fn is_some(&self) -> bool {
+		self.underline || self.overline || self.line_through
+	}
+
+	// text-decoration="underline line-through wavy #ff0000 2"
+	pub fn set_attribute(&mut self, key: &str, val: &str) {
+		match key {
+			"text-decoration" 	=> {
+				for token in val.split_whitespace() {
+					match token {
+						"none"|"NONE" 					=> {
+							self.underline = false;
+							self.overline = false;
+							self.line_through = false;
+						}
+						"underline"|"UNDERLINE" 		=> { self.underline = true }
+						"overline"|"OVERLINE" 			=> { self.overline = true }
+						"line-through"|"LINE-THROUGH" 	=> { self.line_through = true }
+						_ if token.starts_with('#') 	=> { self.set_attribute("text-decoration-color", token) }
+						_ if token.parse::<f32>().is_ok() => { self.set_attribute("text-decoration-thickness", token) }
+						_ 								=> { self.set_attribute("text-decoration-style", token) }
+					}
+				}
+			}
+			"text-decoration-style" 	=> {
+				match val {
+					"solid"|"SOLID" 	=> { self.style = DecorationStyle::Solid }
+					"double"|"DOUBLE" 	=> { self.style = DecorationStyle::Double }
+					"dotted"|"DOTTED" 	=> { self.style = DecorationStyle::Dotted }
+					"wavy"|"WAVY" 		=> { self.style = DecorationStyle::Wavy }
+					_ => { println!("unknown value '{}' for attribute: '{}'", val, key); }
+				}
+			}
+			"text-decoration-thickness" => { self.thickness = Some(val.parse::<f32>().unwrap().abs()) }
+			"text-decoration-color" 	=> {
+				match parse_color(val) {
+					Some(color) => { self.color = Some(color) }
+					None => { println!("wrong value '{}' for attribute: '{}' => #xxxxxx or #xxxxxxxx", val, key); }
+				}
+			}
+			"text-decoration-skip-ink" 	=> {
+				match val {
+					"auto"|"AUTO"|"true"|"TRUE" 	=> { self.skip_ink = true }
+					"none"|"NONE"|"false"|"FALSE" 	=> { self.skip_ink = false }
+					_ => { println!("unknown value '{}' for attribute: '{}'", val, key); }
+				}
+			}
+			_ => {
+				println!("unknown attribute: '{}'", key);
+			}
+		}
+	}
+}
+
+
+// filled box behind the glyphs of a span, on each line it covers
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Background {
+	pub color: Option<ColorRGBA>,
+	pub padding: f32,
+	pub radius: f32,
+}
+
+
+impl Background {
+
+	pub fn new() -> Self {
+		Self {
+			color: None,
+			padding: 0.0,
+			radius: 0.0,
+		}
+	}
+
+	pub fn set_attribute(&mut self, key: &str, val: &str) {
+		match key {
+			"background" 			=> {
+				if val == "none" || val == "NONE" {
+					self.color = None;
+					return;
+				}
+				match parse_color(val) {
+					Some(color) => { self.color = Some(color) }
+					None => { println!("wrong value '{}' for attribute: '{}' => #xxxxxx or #xxxxxxxx", val, key); }
+				}
+			}
+			"background-padding" 	=> { self.padding = val.parse::<f32>().unwrap().abs() }
+			"background-radius" 	=> { self.radius = val.parse::<f32>().unwrap().abs() }
+			_ => {
+				println!("unknown attribute: '{}'", key);
+			}
+		}
+	}
+}
+
+
+// fill of the glyphs, gradients and patterns are stretched over the box of the glyph,
+// of the span on the line or of the block
+#[derive(Clone)]
+pub enum Paint {
+	Solid(ColorRGBA),
+	// angle in degrees like in css, 0 is to the top and 90 to the right
+	LinearGradient { angle: f32, stops: Vec<(f32, ColorRGBA)> },
+	RadialGradient { stops: Vec<(f32, ColorRGBA)> },
+	// the image is repeated from the top left of the box
+	Pattern(Rc<ImgBuffer>),
+}
+
+
+impl Paint {
+
+	// #rrggbb, linear-gradient(angle, #color [pos], ...), radial-gradient(#color [pos], ...),
+	// pattern(name) needs the images of the typer, so it's parsed there
+	pub fn parse(val: &str) -> Option<Self> {
+		let val = val.trim();
+		if val.starts_with('#') {
+			return parse_color(val).map(Paint::Solid);
+		}
+
+		let open = val.find('(')?;
+		if !val.ends_with(')') {return None};
+		let name = &val[..open];
+		let mut args: Vec<&str> = val[open+1..val.len()-1].split(',').map(|e| e.trim()).collect();
+
+		match name {
+			"linear-gradient" => {
+				let angle = args.first()?.trim_end_matches("deg").parse::<f32>().ok()?;
+				args.remove(0);
+				Some(Paint::LinearGradient { angle, stops: parse_stops(&args)? })
+			}
+			"radial-gradient" => {
+				Some(Paint::RadialGradient { stops: parse_stops(&args)? })
+			}
+			_ => None,
+		}
+	}
+
+	pub fn get_color(&self, p: Point<f32>, area: Rect<f32>) -> ColorRGBA {
+		let width = (area.max.x - area.min.x).max(1.0);
+		let height = (area.max.y - area.min.y).max(1.0);
+		let center = (area.min.x + width / 2.0, area.min.y + height / 2.0);
+
+		match self {
+			Paint::Solid(color) => *color,
+			Paint::LinearGradient { angle, stops } => {
+				// the gradient line goes through the center and reaches the corners
+				let (sin, cos) = angle.to_radians().sin_cos();
+				let len = (width * sin).abs() + (height * cos).abs();
+				let t = ((p.x - center.0) * sin - (p.y - center.1) * cos) / len + 0.5;
+				get_stops_color(stops, t)
+			}
+			Paint::RadialGradient { stops } => {
+				// ellipse to the farthest corner
+				let dx = (p.x - center.0) / (width / 2.0);
+				let dy = (p.y - center.1) / (height / 2.0);
+				get_stops_color(stops, (dx * dx + dy * dy).sqrt() / std::f32::consts::SQRT_2)
+			}
+			Paint::Pattern(image) => {
+				if image.width == 0 || image.height == 0 {return [0, 0, 0, 0]};
+				let x = ((p.x - area.min.x).floor() as i64).rem_euclid(image.width as i64) as usize;
+				let y = ((p.y - area.min.y).floor() as i64).rem_euclid(image.height as i64) as usize;
+				let i = (y * image.width + x) * 4;
+				[image.buffer[i], image.buffer[i+1], image.buffer[i+2], image.buffer[i+3]]
+			}
+		}
+	}
+}
+
+
+impl PartialEq for Paint {
+	fn eq(&self, other: &Self) -> bool {
+		match (self, other) {
+			(Paint::Solid(a), Paint::Solid(b)) => a == b,
+			(Paint::LinearGradient { angle: a, stops: a_stops }, Paint::LinearGradient { angle: b, stops: b_stops }) => a == b && a_stops == b_stops,
+			(Paint::RadialGradient { stops: a }, Paint::RadialGradient { stops: b }) => a == b,
+			(Paint::Pattern(a), Paint::Pattern(b)) => Rc::ptr_eq(a, b),
+			_ => false,
+		}
+	}
+}
+
+
+// the pixels of the pattern are left out
+impl fmt::Debug for Paint {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Paint::Solid(color) => write!(f, "Solid({:?})", color),
+			Paint::LinearGradient { angle, stops } => write!(f, "LinearGradient({}, {:?})", angle, stops),
+			Paint::RadialGradient { stops } => write!(f, "RadialGradient({:?})", stops),
+			Paint::Pattern(image) => write!(f, "Pattern({}x{})", image.width, image.height),
+		}
+	}
+}
+
+
+// stops without a position are spread evenly between their neighbours
+fn parse_stops(args: &[&str]) -> Option<Vec<(f32, ColorRGBA)>> {
+	let mut stops: Vec<(Option<f32>, ColorRGBA)> = Vec::new();
+	for arg in args {
+		let mut parts = arg.split_whitespace();
+		let color = parse_color(parts.next()?)?;
+		let position = match parts.next() {
+			Some(position) => Some(position.parse::<f32>().ok()?),
+			None => None,
+		};
+		stops.push((position, color));
+	}
+	if stops.len() < 2 {return None};
+
+	let last = stops.len() - 1;
+	stops[0].0 = Some(stops[0].0.unwrap_or(0.0));
+	stops[last].0 = Some(stops[last].0.unwrap_or(1.0));
+
+	let mut i = 0;
+	while i < last {
+		let next = (i + 1..=last).find(|j| stops[*j].0.is_some()).unwrap();
+		let (from, to) = (stops[i].0.unwrap(), stops[next].0.unwrap());
+		for (j, stop) in stops.iter_mut().enumerate().take(next).skip(i + 1) {
+			stop.0 = Some(from + (to - from) * (j - i) as f32 / (next - i) as f32);
+		}
+		i = next;
+	}
+
+	Some(stops.iter().map(|(position, color)| (position.unwrap(), *color)).collect())
+}
+
+
+fn get_stops_color(stops: &[(f32, ColorRGBA)], t: f32) -> ColorRGBA {
+	let first = stops[0];
+	if t <= first.0 {return first.1};
+
+	for pair in stops.windows(2) {
+		let ((p0, c0), (p1, c1)) = (pair[0], pair[1]);
+		if t <= p1 {
+			let k = if p1 > p0 {(t - p0) / (p1 - p0)} else {1.0};
+			let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * k).round() as u8;
+			return [mix(c0[0], c1[0]), mix(c0[1], c1[1]), mix(c0[2], c1[2]), mix(c0[3], c1[3])];
+		}
+	}
+	stops[stops.len()-1].1
+}
+
+
+// symbol of the <img> in the text
+pub const OBJECT_REPLACEMENT: char = '\u{fffc}';
+
+
+// <img> in the text, it takes the place of one symbol in the line,
+// the size of the image is used if the size is not set
+#[derive(Clone)]
+pub struct InlineImage {
+	pub image: Rc<ImgBuffer>,
+	pub width: f32,
+	pub height: f32,
+	pub align: ImageAlign,
+}
+
+
+impl InlineImage {
+
+	pub fn new(image: Rc<ImgBuffer>) -> Self {
+		Self {
+			width: image.width as f32,
+			height: image.height as f32,
+			image,
+			align: ImageAlign::Baseline,
+		}
+	}
+
+	pub fn has_attribute(key: &str) -> bool {
+		matches!(key, "src"|"width"|"height"|"align")
+	}
+
+	// keeps the aspect ratio, if only one side is set
+	pub fn set_attribute(&mut self, key: &str, val: &str) {
+		let ratio = self.image.width as f32 / (self.image.height as f32).max(1.0);
+		match key {
+			"src" 		=> {}
+			"width" 	=> {
+				self.width = val.parse::<f32>().unwrap().abs();
+				if self.height == self.image.height as f32 {
+					self.height = self.width / ratio.max(f32::EPSILON);
+				}
+			}
+			"height" 	=> {
+				self.height = val.parse::<f32>().unwrap().abs();
+				if self.width == self.image.width as f32 {
+					self.width = self.height * ratio;
+				}
+			}
+			"align" 	=> {
+				match val {
+					"baseline"|"BASELINE" 	=> { self.align = ImageAlign::Baseline }
+					"middle"|"MIDDLE" 		=> { self.align = ImageAlign::Middle }
+					_ => { println!("unknown value '{}' for attribute: '{}'", val, key); }
+				}
+			}
+			_ => {
+				println!("unknown attribute: '{}'", key);
+			}
+		}
+	}
+}
+
+
+// the pixels of the image are left out
+impl fmt::Debug for InlineImage {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "InlineImage({}x{}, {:?})", self.width, self.height, self.align)
+	}
+}
+
+
+// the color of the text if the color is not set, blur is twice the sigma of the gaussian like in css
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextShadow {
+	pub x: f32,
+	pub y: f32,
+	pub blur: f32,
+	pub color: Option<ColorRGBA>,
+}
+
+
+impl TextShadow {
+
+	// x y [blur] [#color]
+	pub fn parse(val: &str) -> Option<Self> {
+		let mut values = Vec::new();
+		let mut color = None;
+		for token in val.split_whitespace() {
+			if token.starts_with('#') {
+				color = Some(parse_color(token)?);
+			} else {
+				values.push(token.trim_end_matches("px").parse::<f32>().ok()?);
+			}
+		}
+
+		match values[..] {
+			[x, y] 			=> Some(Self { x, y, blur: 0.0, color }),
+			[x, y, blur] 	=> Some(Self { x, y, blur: blur.abs(), color }),
+			_ => None,
+		}
+	}
+
+	// text-shadow="2 2 4 #000000, 0 0 8 #ffff00"
+	pub fn parse_list(val: &str) -> Vec<Self> {
+		if val.trim() == "none" {return Vec::new()};
+		val
+			.split(',')
+			.filter(|e| !e.trim().is_empty())
+			.filter_map(|e| {
+				let res = Self::parse(e);
+				if res.is_none() {
+					println!("wrong text shadow: '{}'", e.trim());
+				}
+				res
+			})
+			.collect()
+	}
+}
+
+
+// outline along the path of the glyphs, drawn under the fill, so only the outer half is visible
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stroke {
+	pub color: ColorRGBA,
+	pub width: f32,
+	pub line_join: LineJoin,
+	pub miter_limit: f32,
+}
+
+
+impl Default for Stroke {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+
+impl Stroke {
+
+	pub fn new() -> Self {
+		Self {
+			color: [0, 0, 0, 255],
+			width: 0.0,
+			line_join: LineJoin::Round,
+			miter_limit: 4.0,
+		}
+	}
+
+	pub fn set_attribute(&mut self, key: &str, val: &str) {
+		match key {
+			"stroke-color" 			=> {
+				match parse_color(val) {
+					Some(color) => { self.color = color }
+					None => { println!("wrong value '{}' for attribute: '{}' => #xxxxxx or #xxxxxxxx", val, key); }
+				}
+			}
+			"stroke-width" 			=> { self.width = val.parse::<f32>().unwrap().abs() }
+			"stroke-linejoin" 		=> {
+				match val {
+					"round"|"ROUND" 	=> { self.line_join = LineJoin::Round }
+					"miter"|"MITER" 	=> { self.line_join = LineJoin::Miter }
+					_ => { println!("unknown value '{}' for attribute: '{}'", val, key); }
+				}
+			}
+			"stroke-miterlimit" 	=> { self.miter_limit = val.parse::<f32>().unwrap().max(1.0) }
+			_ => {
+				println!("unknown attribute: '{}'", key);
+			}
+		}
+	}
+}
+
+
+// pixels from the baseline down to the top of each line, from the font of the glyph
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DecorationMetrics {
+	pub underline_offset: f32,
+	pub underline_thickness: f32,
+	pub overline_offset: f32,
+	pub line_through_offset: f32,
+	pub line_through_thickness: f32,
+}
+
+
+#[derive(Debug, Clone)]
 pub struct RenderChunk {
 	pub line_height: f32,
 	pub color: ColorRGBA,
+	pub decoration: TextDecoration,
+	pub decoration_metrics: DecorationMetrics,
+	pub background: Background,
+	pub stroke: Stroke,
+	pub text_shadows: Vec<TextShadow>,
+	pub paint: Paint,
+	pub paint_area: PaintArea,
+	pub opacity: f32,
+	pub blend_mode: BlendMode,
+	pub image: Option<InlineImage>,
+	// text of a ligature glyph, the symbol is only its first char
+	pub cluster: Option<String>,
+	pub baseline_shift: f32,
+	pub ascent: f32,
+	pub descent: f32,
 }
 
 
 #[derive(Debug)]
 pub struct Layout<'a> {
 	pub blocks: Vec<(FormatBlock, RenderBlock<'a>)>,
+	pub truncated: bool,
 	pub width: f32,
 	pub height: f32,
 	pub x: f32,
@@ -72,6 +840,30 @@ pub struct FormatChunk {
 	pub line_height: f32,
 	pub color: ColorRGBA,
 	pub font: Option<String>,
+	pub font_features: Vec<FontFeature>,
+	pub letter_spacing: Length,
+	pub word_spacing: Length,
+	// pixels up from the baseline of the block of the parent chunks, nested shifts are added
+	pub baseline_shift: f32,
+	// shift of the chunk itself, resolved with its final font size
+	pub shift: BaselineShift,
+	// font-size is set on the chunk, super and sub don't make it smaller
+	pub has_font_size: bool,
+	pub text_transform: TextTransform,
+	pub lang: Option<String>,
+	pub decoration: TextDecoration,
+	pub background: Background,
+	pub stroke: Stroke,
+	pub text_shadows: Vec<TextShadow>,
+	// fill of the glyphs, the color if not set
+	pub fill: Option<Paint>,
+	pub fill_area: PaintArea,
+	pub opacity: f32,
+	// opacity of the parent chunks, nested spans are multiplied
+	pub parent_opacity: f32,
+	pub blend_mode: BlendMode,
+	pub image: Option<InlineImage>,
+	pub paragraph: Option<usize>,
 	pub chunks: Vec<FormatChunks>,
 }
 
@@ -84,35 +876,135 @@ impl FormatChunk {
 			line_height: 1.0,
 			color: [0, 0, 0, 255],
 			font: None,
+			font_features: Vec::new(),
+			letter_spacing: Length::Px(0.0),
+			word_spacing: Length::Px(0.0),
+			baseline_shift: 0.0,
+			shift: BaselineShift::Baseline,
+			has_font_size: false,
+			text_transform: TextTransform::None,
+			lang: None,
+			decoration: TextDecoration::new(),
+			background: Background::new(),
+			stroke: Stroke::new(),
+			text_shadows: Vec::new(),
+			fill: None,
+			fill_area: PaintArea::Span,
+			opacity: 1.0,
+			parent_opacity: 1.0,
+			blend_mode: BlendMode::Normal,
+			image: None,
+			paragraph: None,
 			chunks: Vec::new(),
 		}
 	}
 
 	pub fn set_attribute(&mut self, key: &str, val: &str) {
 		match key {
-			"font-size" 	=> { self.font_size = val.parse::<usize>().unwrap() }
+			"font-size" 	=> {
+				self.font_size = val.parse::<usize>().unwrap();
+				self.has_font_size = true;
+			}
 			"line-height" 	=> { self.line_height = val.parse::<f32>().unwrap() }
 			"font" 			=> { self.font = Some(val.to_string()) }
+			"font-features" => {
+				for feature in FontFeature::parse_list(val) {
+					self.font_features.retain(|e| e.tag != feature.tag);
+					self.font_features.push(feature);
+				}
+			}
+			"letter-spacing" 	=> {
+				match Length::parse(val) {
+					Some(length) => { self.letter_spacing = length }
+					None => { println!("wrong value '{}' for attribute: '{}' => px or em", val, key); }
+				}
+			}
+			"word-spacing" 		=> {
+				match Length::parse(val) {
+					Some(length) => { self.word_spacing = length }
+					None => { println!("wrong value '{}' for attribute: '{}' => px or em", val, key); }
+				}
+			}
+			"text-transform" 	=> {
+				match val {
+					"none"|"NONE" 				=> { self.text_transform = TextTransform::None }
+					"uppercase"|"UPPERCASE" 	=> { self.text_transform = TextTransform::Uppercase }
+					"lowercase"|"LOWERCASE" 	=> { self.text_transform = TextTransform::Lowercase }
+					"capitalize"|"CAPITALIZE" 	=> { self.text_transform = TextTransform::Capitalize }
+					_ => { println!("unknown value '{}' for attribute: '{}'", val, key); }
+				}
+			}
+			"lang" 				=> { self.lang = Some(val.to_string()) }
+			"text-shadow" 		=> { self.text_shadows = TextShadow::parse_list(val) }
+			"fill" 				=> {
+				if val == "none" || val == "NONE" {
+					self.fill = None;
+					return;
+				}
+				match Paint::parse(val) {
+					Some(paint) => { self.fill = Some(paint) }
+					None => { println!("wrong value '{}' for attribute: '{}' => #color, linear-gradient(angle, #color [pos], ...), radial-gradient(#color [pos], ...) or pattern(name)", val, key); }
+				}
+			}
+			"opacity" 			=> { self.opacity = val.parse::<f32>().unwrap().clamp(0.0, 1.0) }
+			"blend-mode" 		=> {
+				match val {
+					"normal"|"NORMAL" 		=> { self.blend_mode = BlendMode::Normal }
+					"multiply"|"MULTIPLY" 	=> { self.blend_mode = BlendMode::Multiply }
+					"screen"|"SCREEN" 		=> { self.blend_mode = BlendMode::Screen }
+					"overlay"|"OVERLAY" 	=> { self.blend_mode = BlendMode::Overlay }
+					"darken"|"DARKEN" 		=> { self.blend_mode = BlendMode::Darken }
+					"lighten"|"LIGHTEN" 	=> { self.blend_mode = BlendMode::Lighten }
+					"additive"|"ADDITIVE" 	=> { self.blend_mode = BlendMode::Additive }
+					_ => { println!("unknown value '{}' for attribute: '{}'", val, key); }
+				}
+			}
+			"fill-area" 		=> {
+				match val {
+					"glyph"|"GLYPH" 	=> { self.fill_area = PaintArea::Glyph }
+					"span"|"SPAN" 		=> { self.fill_area = PaintArea::Span }
+					"block"|"BLOCK" 	=> { self.fill_area = PaintArea::Block }
+					_ => { println!("unknown value '{}' for attribute: '{}'", val, key); }
+				}
+			}
+			// super and sub make the parent font size smaller, the shift is resolved with the final size
+			"baseline-shift" 	=> {
+				let shift = match val {
+					"baseline"|"BASELINE" 	=> BaselineShift::Baseline,
+					"super"|"SUPER" 		=> BaselineShift::Super,
+					"sub"|"SUB" 			=> BaselineShift::Sub,
+					_ => {
+						match Length::parse(val) {
+							Some(length) => BaselineShift::Length(length),
+							None => {
+								println!("wrong value '{}' for attribute: '{}' => super, sub, px or em", val, key);
+								return;
+							}
+						}
+					}
+				};
+				let was_smaller = self.shift == BaselineShift::Super || self.shift == BaselineShift::Sub;
+				let is_smaller = shift == BaselineShift::Super || shift == BaselineShift::Sub;
+				if is_smaller && !was_smaller && !self.has_font_size {
+					self.font_size = ((self.font_size as f32 * 0.7).round() as usize).max(1);
+				}
+				self.shift = shift;
+			}
 			"color" 			=> {
-				let err = &format!("wrong color: {}", val);
-				if val.starts_with('#') && val.len() == 7 {
-					self.color = [
-						u8::from_str_radix(val.get(1..3).expect(err), 16).expect(err),
-						u8::from_str_radix(val.get(3..5).expect(err), 16).expect(err),
-						u8::from_str_radix(val.get(5..).expect(err), 16).expect(err),
-						255
-					];
-				} else if val.starts_with('#') && val.len() == 9 {
-					self.color = [
-						u8::from_str_radix(val.get(1..3).expect(err), 16).expect(err),
-						u8::from_str_radix(val.get(3..5).expect(err), 16).expect(err),
-						u8::from_str_radix(val.get(5..7).expect(err), 16).expect(err),
-						u8::from_str_radix(val.get(7..).expect(err), 16).expect(err)
-					];
-				} else {
-					println!("wrong value '{}' for attribute: '{}' => #xxxxxx or #xxxxxxxx", val, key);
+				match parse_color(val) {
+					Some(color) => { self.color = color }
+					None => { println!("wrong value '{}' for attribute: '{}' => #xxxxxx or #xxxxxxxx", val, key); }
 				}
 			}
+			_ if key.starts_with("text-decoration") => {
+				self.decoration.set_attribute(key, val);
+			}
+			_ if key.starts_with("background") => {
+				self.background.set_attribute(key, val);
+			}
+			_ if key.starts_with("stroke") => {
+				self.stroke.set_attribute(key, val);
+			}
 			_ => {
 				println!("unknown attribute: '{}'", key);
 			}
@@ -126,6 +1018,25 @@ impl FormatChunk {
 			line_height: self.line_height,
 			color: self.color,
 			font: self.font.clone(),
+			font_features: self.font_features.clone(),
+			letter_spacing: self.letter_spacing,
+			word_spacing: self.word_spacing,
+			baseline_shift: self.get_baseline_shift(),
+			shift: BaselineShift::Baseline,
+			has_font_size: false,
+			text_transform: self.text_transform,
+			lang: self.lang.clone(),
+			decoration: self.decoration,
+			background: self.background,
+			stroke: self.stroke,
+			text_shadows: self.text_shadows.clone(),
+			fill: self.fill.clone(),
+			fill_area: self.fill_area,
+			opacity: 1.0,
+			parent_opacity: self.parent_opacity * self.opacity,
+			blend_mode: self.blend_mode,
+			image: None,
+			paragraph: self.paragraph,
 			chunks: Vec::new(),
 		}
 	}
@@ -139,10 +1050,60 @@ impl FormatChunk {
 		}
 	}
 
+	// pixels up from the baseline of the block
+	pub fn get_baseline_shift(&self) -> f32 {
+		self.baseline_shift + self.shift.to_px(self.font_size as f32)
+	}
+
+	pub fn get_scaled_font_size(&self, factor: f32) -> usize {
+		if factor == 1.0 {return self.font_size};
+		((self.font_size as f32 * factor).round() as usize).max(1)
+	}
+
+	// the lengths in px are scaled with the font, like in the format with the font scale
+	pub fn scale_font_size(&mut self, factor: f32) {
+		self.font_size = self.get_scaled_font_size(factor);
+		self.baseline_shift *= factor;
+		if let BaselineShift::Length(length) = self.shift {
+			self.shift = BaselineShift::Length(length.scale(factor));
+		}
+		self.letter_spacing = self.letter_spacing.scale(factor);
+		self.word_spacing = self.word_spacing.scale(factor);
+		for chunk in self.chunks.iter_mut() {
+			if let FormatChunks::Chunk(chunk) = chunk {
+				chunk.scale_font_size(factor);
+			}
+		}
+	}
+
+	// smallest and largest font size of the chunks with text
+	pub fn font_size_range(&self) -> Option<(usize, usize)> {
+		self.iter().fold(None, |range, (chunk, _)| {
+			match range {
+				None => Some((chunk.font_size, chunk.font_size)),
+				Some((min, max)) => Some((min.min(chunk.font_size), max.max(chunk.font_size))),
+			}
+		})
+	}
+
 	pub fn get_render_chunk (&self) -> RenderChunk {
 		RenderChunk{
 			line_height: self.line_height,
 			color: self.color,
+			decoration: self.decoration,
+			decoration_metrics: DecorationMetrics::default(),
+			background: self.background,
+			stroke: self.stroke,
+			text_shadows: self.text_shadows.clone(),
+			paint: self.fill.clone().unwrap_or(Paint::Solid(self.color)),
+			paint_area: self.fill_area,
+			opacity: self.parent_opacity * self.opacity,
+			blend_mode: self.blend_mode,
+			image: self.image.clone(),
+			cluster: None,
+			baseline_shift: 0.0,
+			ascent: 0.0,
+			descent: 0.0,
 		}
 	}
 }
@@ -150,12 +1111,45 @@ impl FormatChunk {
 
 #[derive(Debug)]
 pub struct FormatBlock {
+	pub id: Option<String>,
+	pub next: Option<String>,
 	pub text_align: TextAlignHorizontal,
+	pub vertical_align: TextAlignVertical,
 	pub break_word: bool,
+	pub line_breaking: LineBreaking,
+	pub space_stretch: f32,
+	pub space_shrink: f32,
+	pub hyphen_penalty: f32,
+	pub tab_stops: Vec<TabStop>,
+	pub tab_interval: f32,
+	pub decimal_separator: char,
+	pub fit: BlockFit,
+	pub overflow: Overflow,
+	pub max_lines: Option<usize>,
+	pub min_font_size: Option<usize>,
+	pub max_font_size: Option<usize>,
 	pub width: f32,
 	pub height: f32,
 	pub x: f32,
 	pub y: f32,
+	pub margin_top: f32,
+	pub margin_bottom: f32,
+	// padding and border are drawn around the block box
+	pub padding: f32,
+	pub border_width: f32,
+	pub border_color: ColorRGBA,
+	pub columns: usize,
+	pub column_gap: f32,
+	pub column_balance: bool,
+	pub flow: bool,
+	pub flow_container: usize,
+	pub page_break_before: bool,
+	pub keep_together: bool,
+	pub orphans: usize,
+	pub widows: usize,
+	pub exclusions: Vec<Exclusion>,
+	pub paragraphs: Vec<Paragraph>,
+	pub table: Option<Table>,
 	pub chunk: FormatChunk,
 }
 
@@ -164,12 +1158,44 @@ impl FormatBlock {
 
 	pub fn new() -> Self {
 		Self {
+			id: None,
+			next: None,
 			text_align: TextAlignHorizontal::Left,
+			vertical_align: TextAlignVertical::Top,
 			break_word: false,
+			line_breaking: LineBreaking::Greedy,
+			space_stretch: 0.5,
+			space_shrink: 0.33,
+			hyphen_penalty: 50.0,
+			tab_stops: Vec::new(),
+			tab_interval: 48.0,
+			decimal_separator: '.',
+			fit: BlockFit::None,
+			overflow: Overflow::Visible,
+			max_lines: None,
+			min_font_size: None,
+			max_font_size: None,
 			width: 0.0,
 			height: 0.0,
 			x: 0.0,
 			y: 0.0,
+			margin_top: 0.0,
+			margin_bottom: 0.0,
+			padding: 0.0,
+			border_width: 0.0,
+			border_color: [0, 0, 0, 255],
+			columns: 1,
+			column_gap: 0.0,
+			column_balance: false,
+			flow: false,
+			flow_container: 0,
+			page_break_before: false,
+			keep_together: false,
+			orphans: 2,
+			widows: 2,
+			exclusions: Vec::new(),
+			paragraphs: Vec::new(),
+			table: None,
 			chunk: FormatChunk::new(),
 		}
 	}
@@ -186,10 +1212,109 @@ impl FormatBlock {
 				}
 			}
 
+			"id" 			=> { self.id = Some(val.to_string()) }
+			"next" 			=> { self.next = Some(val.to_string()) }
+
+			"vertical-align" 		=> {
+				match val {
+					"top"|"TOP" 			=> { self.vertical_align = TextAlignVertical::Top }
+					"middle"|"MIDDLE" 		=> { self.vertical_align = TextAlignVertical::Middle }
+					"bottom"|"BOTTOM" 		=> { self.vertical_align = TextAlignVertical::Bottom }
+					"baseline"|"BASELINE" 	=> { self.vertical_align = TextAlignVertical::Baseline }
+					_ => { println!("unknown value '{}' for attribute: '{}'", val, key); }
+				}
+			}
+
+			"fit" 			=> {
+				match val {
+					"none"|"NONE" 		=> { self.fit = BlockFit::None }
+					"shrink"|"SHRINK" 	=> { self.fit = BlockFit::Shrink }
+					"grow"|"GROW" 		=> { self.fit = BlockFit::Grow }
+					"both"|"BOTH" 		=> { self.fit = BlockFit::Both }
+					_ => { println!("unknown value '{}' for attribute: '{}'", val, key); }
+				}
+			}
+			"overflow" 		=> {
+				match val {
+					"visible"|"VISIBLE" 	=> { self.overflow = Overflow::Visible }
+					"clip"|"CLIP" 			=> { self.overflow = Overflow::Clip }
+					"ellipsis"|"ELLIPSIS" 	=> { self.overflow = Overflow::Ellipsis }
+					_ => { println!("unknown value '{}' for attribute: '{}'", val, key); }
+				}
+			}
+			"max-lines" 	=> { self.max_lines = Some(val.parse::<usize>().unwrap()) }
+			"min-font-size" => { self.min_font_size = Some(val.parse::<usize>().unwrap().max(1)) }
+			"max-font-size" => { self.max_font_size = Some(val.parse::<usize>().unwrap().max(1)) }
 			"width" 		=> { self.width = val.parse::<f32>().unwrap().abs() }
 			"height" 		=> { self.height = val.parse::<f32>().unwrap().abs() }
 			"x" 			=> { self.x = val.parse::<f32>().unwrap() }
 			"y" 			=> { self.y = val.parse::<f32>().unwrap() }
+			"margin-top" 	=> { self.margin_top = val.parse::<f32>().unwrap() }
+			"margin-bottom" => { self.margin_bottom = val.parse::<f32>().unwrap() }
+			"padding" 		=> { self.padding = val.parse::<f32>().unwrap().abs() }
+			"border" 		=> { self.border_width = val.parse::<f32>().unwrap().abs() }
+			"border-color" 	=> {
+				match parse_color(val) {
+					Some(color) => { self.border_color = color }
+					None => { println!("wrong value '{}' for attribute: '{}' => #xxxxxx or #xxxxxxxx", val, key); }
+				}
+			}
+			"columns" 		=> { self.columns = val.parse::<usize>().unwrap().max(1) }
+			"column-gap" 	=> { self.column_gap = val.parse::<f32>().unwrap().abs() }
+			"column-fill" 	=> {
+				match val {
+					"balance"|"BALANCE" => { self.column_balance = true }
+					"auto"|"AUTO" 		=> { self.column_balance = false }
+					_ => { println!("unknown value '{}' for attribute: '{}'", val, key); }
+				}
+			}
+			// the lines with tabs and the blocks with exclusions are broken greedily even with optimal
+			"line-break" 	=> {
+				match val {
+					"greedy"|"GREEDY" 		=> { self.line_breaking = LineBreaking::Greedy }
+					"optimal"|"OPTIMAL" 	=> { self.line_breaking = LineBreaking::Optimal }
+					_ => { println!("unknown value '{}' for attribute: '{}'", val, key); }
+				}
+			}
+			"space-stretch" 	=> { self.space_stretch = val.parse::<f32>().unwrap().abs() }
+			"space-shrink" 		=> { self.space_shrink = val.parse::<f32>().unwrap().abs().min(1.0) }
+			"hyphen-penalty" 	=> { self.hyphen_penalty = val.parse::<f32>().unwrap() }
+			"tab-stops" 		=> {
+				self.tab_stops = val
+					.split_whitespace()
+					.filter_map(|stop| {
+						let res = TabStop::parse(stop);
+						if res.is_none() {
+							println!("wrong tab stop '{}' for attribute: '{}' => position[:left|right|center|decimal[:leader]]", stop, key);
+						}
+						res
+					})
+					.collect();
+				self.tab_stops.sort_by(|a, b| a.position.partial_cmp(&b.position).unwrap_or(Ordering::Equal));
+			}
+			"tab-interval" 		=> { self.tab_interval = val.parse::<f32>().unwrap().abs().max(1.0) }
+			"decimal-separator" => {
+				match val.chars().next() {
+					Some(c) => { self.decimal_separator = c }
+					None => { println!("wrong value '{}' for attribute: '{}'", val, key); }
+				}
+			}
+			"page-break-before" 	=> {
+				match val {
+					"true"|"TRUE"|"1"|"yes"|"always" 	=> { self.page_break_before = true }
+					"false"|"FALSE"|"0"|"no"|"auto" 	=> { self.page_break_before = false }
+					_ => { println!("unknown value '{}' for attribute: '{}'", val, key); }
+				}
+			}
+			"keep-together" 	=> {
+				match val {
+					"true"|"TRUE"|"1"|"yes" 	=> { self.keep_together = true }
+					"false"|"FALSE"|"0"|"no" 	=> { self.keep_together = false }
+					_ => { println!("unknown value '{}' for attribute: '{}'", val, key); }
+				}
+			}
+			"orphans" 		=> { self.orphans = val.parse::<usize>().unwrap() }
+			"widows" 		=> { self.widows = val.parse::<usize>().unwrap() }
 			"break_word" 		=> {
 				match val {
 					"true"|"TRUE"|"1"|"yes" 	=> { self.break_word = true }
@@ -206,26 +1331,115 @@ impl FormatBlock {
 
 	pub fn new_empty(&self) -> Self {
 		let res = Self {
+			id: None,
+			next: None,
 			break_word: self.break_word,
+			line_breaking: self.line_breaking,
+			space_stretch: self.space_stretch,
+			space_shrink: self.space_shrink,
+			hyphen_penalty: self.hyphen_penalty,
+			tab_stops: self.tab_stops.clone(),
+			tab_interval: self.tab_interval,
+			decimal_separator: self.decimal_separator,
+			fit: self.fit,
+			overflow: self.overflow,
+			max_lines: self.max_lines,
+			min_font_size: self.min_font_size,
+			max_font_size: self.max_font_size,
 			width: self.width,
 			height: self.height,
 			x: self.x,
 			y: self.y,
+			margin_top: self.margin_top,
+			margin_bottom: self.margin_bottom,
+			padding: self.padding,
+			border_width: self.border_width,
+			border_color: self.border_color,
+			columns: self.columns,
+			column_gap: self.column_gap,
+			column_balance: self.column_balance,
+			flow: self.flow,
+			flow_container: self.flow_container,
+			page_break_before: self.page_break_before,
+			keep_together: self.keep_together,
+			orphans: self.orphans,
+			widows: self.widows,
+			exclusions: Vec::new(),
+			paragraphs: Vec::new(),
+			table: None,
 			text_align: self.text_align,
+			vertical_align: self.vertical_align,
 			chunk: self.chunk.new_empty(),
 		};
 
 		res
 	}
 
+	// the first stop after x, or the next one of the default interval
+	pub fn get_tab_stop(&self, x: f32) -> TabStop {
+		match self.tab_stops.iter().find(|stop| stop.position > x) {
+			Some(stop) => *stop,
+			None => TabStop {
+				position: ((x / self.tab_interval).floor() + 1.0) * self.tab_interval,
+				align: TabAlign::Left,
+				leader: None,
+			},
+		}
+	}
+
+	pub fn get_column_width(&self) -> f32 {
+		if self.width == 0.0 || self.columns < 2 {return self.width};
+		((self.width - self.column_gap * (self.columns - 1) as f32) / self.columns as f32).max(1.0)
+	}
+
+	// widest part of the column, free from exclusions, in the band top..bottom
+	pub fn get_free_band(&self, x: f32, width: f32, top: f32, bottom: f32) -> Option<(f32, f32)> {
+		let mut extents: Vec<(f32, f32)> = self.exclusions
+			.iter()
+			.filter_map(|e| e.get_extent(top, bottom))
+			.collect();
+		extents.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+		let mut best: Option<(f32, f32)> = None;
+		let mut start = x;
+		for (min, max) in extents.into_iter().chain(Some((x + width, x + width))) {
+			let end = min.min(x + width);
+			if end - start > best.map_or(0.0, |b| b.1) {
+				best = Some((start, end - start));
+			}
+			start = start.max(max);
+		}
+		best
+	}
+
+	// baseline: the last line sits on the bottom edge of the block
+	pub fn get_vertical_offset(&self, render_block: &RenderBlock) -> f32 {
+		if self.height == 0.0 {return 0.0};
+
+		match self.vertical_align {
+			TextAlignVertical::Top => 0.0,
+			TextAlignVertical::Middle => (self.height - render_block.height) / 2.0,
+			TextAlignVertical::Bottom => self.height - render_block.height,
+			TextAlignVertical::Baseline => {
+				match render_block.baselines().last() {
+					Some(baseline) => self.height - baseline,
+					None => 0.0,
+				}
+			}
+		}
+	}
+
 	pub fn to_render_block <'a> (&self) -> RenderBlock<'a> {
 		let mut b = RenderBlock {
 			text_align: self.text_align,
+			truncated: false,
+			line_offset: 0,
 			width: self.width,
 			height: self.height,
 			x: self.x,
 			y: self.y,
 			lines: Vec::new(),
+			cells: Vec::new(),
 		};
 		b.add_line();
 		b
@@ -236,23 +1450,41 @@ impl FormatBlock {
 
 #[derive(Debug)]
 pub struct Line<'a> {
+	pub x: f32,
+	pub y: f32,
+	pub max_width: f32,
 	pub width: f32,
+	pub ascent: f32,
 	pub descent: f32,
 	pub height: f32,
 	pub chars_width: f32,
+	pub indent: f32,
+	pub text_align: Option<TextAlignHorizontal>,
 	pub force_break: bool,
+	// a word longer than the line, broken without a break opportunity
+	pub split_word: bool,
 	pub glyphs: Vec<(ScaledGlyph<'a>, RenderChunk, char, f32)>,
+	// drawn before x
+	pub marker: Vec<(ScaledGlyph<'a>, RenderChunk, char, f32)>,
 }
 
 impl <'a> Line<'a> {
 	pub fn new() -> Self {
 		Self {
+			x: 0.0,
+			y: 0.0,
+			max_width: 0.0,
 			width: 0.0,
+			ascent: 0.0,
 			descent: 0.0,
 			height: 0.0,
 			chars_width: 0.0,
+			indent: 0.0,
+			text_align: None,
 			force_break: false,
+			split_word: false,
 			glyphs: Vec::new(),
+			marker: Vec::new(),
 		}
 	}
 
@@ -263,10 +1495,14 @@ impl <'a> Line<'a> {
 pub struct RenderBlock<'a> {
 	pub lines: Vec<Line<'a>>,
 	pub text_align: TextAlignHorizontal,
+	pub truncated: bool,
+	pub line_offset: usize,
 	pub width: f32,
 	pub height: f32,
 	pub x: f32,
 	pub y: f32,
+	// table cells, row by row
+	pub cells: Vec<RenderBlock<'a>>,
 }
 
 
@@ -284,6 +1520,121 @@ impl <'a> RenderBlock<'a> {
 		let i = self.lines.len();
 		&mut self.lines[i-2]
 	}
+
+	pub fn update_size(&mut self) {
+		let mut width: f32 = 0.0;
+		let mut height: f32 = 0.0;
+		let count = self.lines.len();
+
+		for i in 0..count {
+			let line_width = self.lines[i].glyphs
+				.iter()
+				.map(|e| e.3)
+				.sum();
+			let line = &mut self.lines[i];
+			line.width = line_width;
+			width = width.max(line.x + line_width);
+
+			let mut bottom = line.y + line.height;
+			if i == count-1 || self.lines[i+1].y <= self.lines[i].y {
+				bottom -= self.lines[i].descent;
+			}
+			height = height.max(bottom);
+		}
+
+		self.width = width;
+		self.height = height;
+	}
+
+	// baseline of every line, relative to the top of the block
+	pub fn baselines(&self) -> Vec<f32> {
+		self.lines
+			.iter()
+			.map(|line| line.y + line.height + line.descent)
+			.collect()
+	}
+
+	// moves the first `count` lines into a new block, the rest start at the top of this one
+	pub fn split_lines(&mut self, count: usize) -> Self {
+		let rest = self.lines.split_off(count);
+		let lines = std::mem::replace(&mut self.lines, rest);
+
+		if let Some(top) = self.lines.first().map(|line| line.y) {
+			self.lines.iter_mut().for_each(|line| line.y -= top);
+		}
+
+		let mut block = Self {
+			lines,
+			text_align: self.text_align,
+			truncated: false,
+			line_offset: self.line_offset,
+			width: 0.0,
+			height: 0.0,
+			x: self.x,
+			y: self.y,
+			cells: Vec::new(),
+		};
+		self.line_offset += count;
+
+		block.update_size();
+		self.update_size();
+		block
+	}
+}
+
+
+#[derive(Debug, Clone, Copy)]
+pub struct LineMetrics {
+	pub width: f32,
+	pub height: f32,
+	pub ascent: f32,
+	pub descent: f32,
+	pub baseline: f32,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct BlockMetrics {
+	pub width: f32,
+	pub height: f32,
+	pub x: f32,
+	pub y: f32,
+	pub lines: Vec<LineMetrics>,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Metrics {
+	pub width: f32,
+	pub height: f32,
+	pub x: f32,
+	pub y: f32,
+	pub blocks: Vec<BlockMetrics>,
+}
+
+
+impl BlockMetrics {
+	pub fn new(format_block: &FormatBlock, render_block: &RenderBlock) -> Self {
+		let offset = format_block.get_vertical_offset(render_block);
+
+		Self {
+			width: render_block.width,
+			height: render_block.height,
+			x: render_block.x,
+			y: render_block.y,
+			lines: render_block.lines
+				.iter()
+				.zip(render_block.baselines())
+				.map(|(line, baseline)| LineMetrics {
+					width: line.width,
+					height: line.height,
+					ascent: line.ascent,
+					descent: line.descent,
+					baseline: baseline + offset,
+				})
+				.collect(),
+		}
+	}
 }
 
 
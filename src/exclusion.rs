@@ -0,0 +1,128 @@
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ExclusionShape {
+	Rect,
+	Circle,
+	Polygon,
+}
+
+
+// coordinates are relative to the block
+#[derive(Debug, Clone)]
+pub struct Exclusion {
+	pub shape: ExclusionShape,
+	pub x: f32,
+	pub y: f32,
+	pub width: f32,
+	pub height: f32,
+	pub radius: f32,
+	pub points: Vec<(f32, f32)>,
+	pub margin: f32,
+}
+
+
+impl Default for Exclusion {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+
+impl Exclusion {
+
+	pub fn new() -> Self {
+		Self {
+			shape: ExclusionShape::Rect,
+			x: 0.0,
+			y: 0.0,
+			width: 0.0,
+			height: 0.0,
+			radius: 0.0,
+			points: Vec::new(),
+			margin: 0.0,
+		}
+	}
+
+	pub fn set_attribute(&mut self, key: &str, val: &str) {
+		match key {
+			"shape" 		=> {
+				match val {
+					"rect"|"RECT" 			=> { self.shape = ExclusionShape::Rect }
+					"circle"|"CIRCLE" 		=> { self.shape = ExclusionShape::Circle }
+					"polygon"|"POLYGON" 	=> { self.shape = ExclusionShape::Polygon }
+					_ => { println!("unknown value '{}' for attribute: '{}'", val, key); }
+				}
+			}
+			"x" 			=> { self.x = val.parse::<f32>().unwrap() }
+			"y" 			=> { self.y = val.parse::<f32>().unwrap() }
+			"width" 		=> { self.width = val.parse::<f32>().unwrap().abs() }
+			"height" 		=> { self.height = val.parse::<f32>().unwrap().abs() }
+			"r" 			=> { self.radius = val.parse::<f32>().unwrap().abs() }
+			"margin" 		=> { self.margin = val.parse::<f32>().unwrap().abs() }
+			"points" 		=> {
+				self.points = val
+					.split_whitespace()
+					.filter_map(|point| {
+						let mut xy = point.split(',').map(|e| e.trim().parse::<f32>());
+						match (xy.next(), xy.next()) {
+							(Some(Ok(x)), Some(Ok(y))) => Some((x, y)),
+							_ => {
+								println!("wrong point '{}' for attribute: '{}' => x,y x,y ...", point, key);
+								None
+							}
+						}
+					})
+					.collect();
+			}
+			_ => {
+				println!("unknown attribute: '{}'", key);
+			}
+		}
+	}
+
+
+	// horizontal extent of the shape (with margin) inside the band top..bottom
+	pub fn get_extent(&self, top: f32, bottom: f32) -> Option<(f32, f32)> {
+		let top = top - self.margin;
+		let bottom = bottom + self.margin;
+
+		let (min, max) = match self.shape {
+			ExclusionShape::Rect => {
+				if self.y + self.height <= top || self.y >= bottom {return None};
+				(self.x, self.x + self.width)
+			}
+			ExclusionShape::Circle => {
+				let radius = self.radius + self.margin;
+				let dy = (self.y - self.y.max(top + self.margin).min(bottom - self.margin)).abs();
+				if dy >= radius {return None};
+				let half = (radius * radius - dy * dy).sqrt();
+				return Some((self.x - half, self.x + half));
+			}
+			ExclusionShape::Polygon => {
+				let mut min = f32::MAX;
+				let mut max = f32::MIN;
+				let count = self.points.len();
+
+				for i in 0..count {
+					let (x1, y1) = self.points[i];
+					let (x2, y2) = self.points[(i + 1) % count];
+
+					if (y1 <= top && y2 <= top) || (y1 >= bottom && y2 >= bottom) {continue};
+
+					// part of the edge inside the band
+					let at = |y: f32| if y1 == y2 {x1} else {x1 + (x2 - x1) * (y - y1) / (y2 - y1)};
+					let from = y1.min(y2).max(top);
+					let to = y1.max(y2).min(bottom);
+					for x in &[at(from), at(to)] {
+						min = min.min(*x);
+						max = max.max(*x);
+					}
+				}
+
+				if min > max {return None};
+				(min, max)
+			}
+		};
+
+		Some((min - self.margin, max + self.margin))
+	}
+}
@@ -14,13 +14,48 @@ pub struct ImgBufferRef<'a> {
 	pub height: usize,
 }
 
+// how the color is mixed with the color under it, before the alpha blending
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BlendMode {
+	Normal,
+	Multiply,
+	Screen,
+	Overlay,
+	Darken,
+	Lighten,
+	Additive,
+}
+
+
+impl BlendMode {
+
+	pub fn blend(self, backdrop: f32, source: f32) -> f32 {
+		match self {
+			BlendMode::Normal 	=> source,
+			BlendMode::Multiply => backdrop * source,
+			BlendMode::Screen 	=> backdrop + source - backdrop * source,
+			BlendMode::Overlay 	=> {
+				if backdrop <= 0.5 {
+					2.0 * backdrop * source
+				} else {
+					1.0 - 2.0 * (1.0 - backdrop) * (1.0 - source)
+				}
+			}
+			BlendMode::Darken 	=> backdrop.min(source),
+			BlendMode::Lighten 	=> backdrop.max(source),
+			BlendMode::Additive => (backdrop + source).min(1.0),
+		}
+	}
+}
+
+
 pub trait ImgBufferTrait {
 	#[inline] fn get_pixel_mut(&mut self, x: usize, y: usize) -> &mut [u8];
 	#[inline] fn width(&self) -> usize;
 	#[inline] fn height(&self) -> usize;
 	#[inline] fn get_buffer_mut(&mut self) -> &mut Vec<u8>;
 
-	fn blend_pixel (&mut self, x:usize ,y:usize, pixel: &[u8;4], v:f32) {
+	fn blend_pixel (&mut self, x:usize ,y:usize, pixel: &[u8;4], v:f32, mode: BlendMode) {
 		if 
 			x > self.width()-1 || 
 			y > self.height()-1
@@ -36,9 +71,10 @@ pub trait ImgBufferTrait {
 		let alpha = f_pixel[3] * v;
 		let o_alpha = o_pixel[3] as f32 / 255.0;
 
-		o_pixel[0] = ((((o_pixel[0] as f32 / 255.0) * (1.0-alpha)) + (f_pixel[0]*alpha)) * 255.0) as u8;
-		o_pixel[1] = ((((o_pixel[1] as f32 / 255.0) * (1.0-alpha)) + (f_pixel[1]*alpha)) * 255.0) as u8;
-		o_pixel[2] = ((((o_pixel[2] as f32 / 255.0) * (1.0-alpha)) + (f_pixel[2]*alpha)) * 255.0) as u8;
+		for i in 0..3 {
+			let backdrop = o_pixel[i] as f32 / 255.0;
+			o_pixel[i] = (((backdrop * (1.0-alpha)) + (mode.blend(backdrop, f_pixel[i])*alpha)) * 255.0) as u8;
+		}
 		o_pixel[3] = ( (alpha * v).max(o_alpha) * 255.0 ) as u8;
 	}
 
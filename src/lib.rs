@@ -1,11 +1,20 @@
 pub extern crate rusttype;
 
 mod chunk;
+mod exclusion;
 mod img_buffer;
+mod list;
+mod mask;
+mod opentype;
 mod rusttype_renderer;
+mod table;
 mod typer;
 
 pub use chunk::*;
+pub use exclusion::*;
 pub use img_buffer::*;
+pub use list::*;
+pub use opentype::*;
 pub use rusttype_renderer::*;
+pub use table::*;
 pub use typer::*;
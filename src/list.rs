@@ -0,0 +1,133 @@
+use chunk::Paragraph;
+
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ListStyle {
+	None,
+	Disc,
+	Circle,
+	Square,
+	Decimal,
+	LowerAlpha,
+	UpperAlpha,
+	LowerRoman,
+	UpperRoman,
+}
+
+
+impl ListStyle {
+
+	pub fn get_marker(&self, number: usize) -> Option<String> {
+		match self {
+			ListStyle::None 		=> None,
+			ListStyle::Disc 		=> Some("•".to_string()),
+			ListStyle::Circle 		=> Some("◦".to_string()),
+			ListStyle::Square 		=> Some("▪".to_string()),
+			ListStyle::Decimal 		=> Some(format!("{}.", number)),
+			ListStyle::LowerAlpha 	=> Some(format!("{}.", to_alpha(number))),
+			ListStyle::UpperAlpha 	=> Some(format!("{}.", to_alpha(number).to_uppercase())),
+			ListStyle::LowerRoman 	=> Some(format!("{}.", to_roman(number))),
+			ListStyle::UpperRoman 	=> Some(format!("{}.", to_roman(number).to_uppercase())),
+		}
+	}
+}
+
+
+// <ul> or <ol>, numbers its items and gives them the gutter for the marker
+#[derive(Debug, Clone)]
+pub struct List {
+	pub style: ListStyle,
+	pub counter: usize,
+	pub indent: f32,
+	pub margin_left: f32,
+	pub paragraph: Paragraph,
+}
+
+
+impl List {
+
+	// nested unordered lists change the bullet like browsers do
+	pub fn new(is_ordered: bool, level: usize) -> Self {
+		let style = match (is_ordered, level % 3) {
+			(true, _) 	=> ListStyle::Decimal,
+			(false, 0) 	=> ListStyle::Disc,
+			(false, 1) 	=> ListStyle::Circle,
+			(false, _) 	=> ListStyle::Square,
+		};
+
+		Self {
+			style,
+			counter: 1,
+			indent: 24.0,
+			margin_left: 0.0,
+			paragraph: Paragraph::new(),
+		}
+	}
+
+	pub fn has_attribute(key: &str) -> bool {
+		matches!(key, "list-style"|"start"|"indent")
+	}
+
+	pub fn set_attribute(&mut self, key: &str, val: &str) {
+		match key {
+			"list-style" 	=> {
+				match val {
+					"none"|"NONE" 					=> { self.style = ListStyle::None }
+					"disc"|"DISC" 					=> { self.style = ListStyle::Disc }
+					"circle"|"CIRCLE" 				=> { self.style = ListStyle::Circle }
+					"square"|"SQUARE" 				=> { self.style = ListStyle::Square }
+					"decimal"|"DECIMAL" 			=> { self.style = ListStyle::Decimal }
+					"lower-alpha"|"LOWER-ALPHA" 	=> { self.style = ListStyle::LowerAlpha }
+					"upper-alpha"|"UPPER-ALPHA" 	=> { self.style = ListStyle::UpperAlpha }
+					"lower-roman"|"LOWER-ROMAN" 	=> { self.style = ListStyle::LowerRoman }
+					"upper-roman"|"UPPER-ROMAN" 	=> { self.style = ListStyle::UpperRoman }
+					_ => { println!("unknown value '{}' for attribute: '{}'", val, key); }
+				}
+			}
+			"start" 		=> { self.counter = val.parse::<usize>().unwrap() }
+			"indent" 		=> { self.indent = val.parse::<f32>().unwrap().abs() }
+			_ => {
+				println!("unknown attribute: '{}'", key);
+			}
+		}
+	}
+
+	// paragraph of the next <li>
+	pub fn next_item(&mut self) -> Paragraph {
+		let mut paragraph = self.paragraph.clone();
+		paragraph.margin_left = self.margin_left + self.indent;
+		paragraph.marker = self.style.get_marker(self.counter);
+		self.counter += 1;
+		paragraph
+	}
+}
+
+
+// 1 => a, 26 => z, 27 => aa
+fn to_alpha(mut number: usize) -> String {
+	let mut res = Vec::new();
+	while number > 0 {
+		number -= 1;
+		res.push((b'a' + (number % 26) as u8) as char);
+		number /= 26;
+	}
+	res.iter().rev().collect()
+}
+
+
+fn to_roman(mut number: usize) -> String {
+	let numerals = [
+		(1000, "m"), (900, "cm"), (500, "d"), (400, "cd"),
+		(100, "c"), (90, "xc"), (50, "l"), (40, "xl"),
+		(10, "x"), (9, "ix"), (5, "v"), (4, "iv"), (1, "i"),
+	];
+
+	let mut res = String::new();
+	for (value, numeral) in numerals.iter() {
+		while number >= *value {
+			res.push_str(numeral);
+			number -= value;
+		}
+	}
+	res
+}
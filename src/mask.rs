@@ -0,0 +1,107 @@
+extern crate rusttype;
+
+use img_buffer::{ImgBufferTrait, ColorRGBA, BlendMode};
+use self::rusttype::Rect;
+
+
+// coverage of an area of the image, for the effects drawn from the shape of the glyphs
+#[derive(Debug, Clone)]
+pub(crate) struct Mask {
+	pub(crate) x: i32,
+	pub(crate) y: i32,
+	pub(crate) width: usize,
+	pub(crate) height: usize,
+	pub(crate) data: Vec<f32>,
+}
+
+
+impl Mask {
+
+	pub(crate) fn new(rect: Rect<i32>) -> Self {
+		let width = (rect.max.x - rect.min.x).max(0) as usize;
+		let height = (rect.max.y - rect.min.y).max(0) as usize;
+		Self {
+			x: rect.min.x,
+			y: rect.min.y,
+			width,
+			height,
+			data: vec![0.0; width * height],
+		}
+	}
+
+	// coverages of the overlapping glyphs are combined, not summed
+	pub(crate) fn add(&mut self, x: i32, y: i32, v: f32) {
+		let (x, y) = (x - self.x, y - self.y);
+		if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {return};
+		let e = &mut self.data[y as usize * self.width + x as usize];
+		*e = *e + v - *e * v;
+	}
+
+	// three box blurs are close to a gaussian blur with the sigma
+	pub(crate) fn blur(&mut self, sigma: f32) {
+		if sigma < 0.5 {return};
+		for size in get_box_sizes(sigma, 3) {
+			let radius = (size - 1) / 2;
+			self.box_blur(radius, true);
+			self.box_blur(radius, false);
+		}
+	}
+
+	pub(crate) fn draw<T: ImgBufferTrait>(&self, buffer: &mut T, color: &ColorRGBA, blend_mode: BlendMode, clip: Option<Rect<i32>>) {
+		let buffer_width = buffer.width() as i32;
+		let buffer_height = buffer.height() as i32;
+
+		for (i, v) in self.data.iter().enumerate() {
+			if *v < 1.0 / 255.0 {continue};
+			let x = self.x + (i % self.width) as i32;
+			let y = self.y + (i / self.width) as i32;
+			if x < 0 || y < 0 || x >= buffer_width || y >= buffer_height {continue};
+			if let Some(clip) = clip {
+				if x < clip.min.x || y < clip.min.y || x >= clip.max.x || y >= clip.max.y {continue};
+			}
+			buffer.blend_pixel(x as usize, y as usize, color, *v, blend_mode);
+		}
+	}
+
+	// running sum over the rows or the columns, outside of the mask is empty
+	fn box_blur(&mut self, radius: usize, is_horizontal: bool) {
+		let (count, len, step, stride) = if is_horizontal {
+			(self.height, self.width, 1, self.width)
+		} else {
+			(self.width, self.height, self.width, 1)
+		};
+		let size = (radius * 2 + 1) as f32;
+		let mut line = vec![0.0; len];
+
+		for n in 0..count {
+			let start = n * stride;
+			for (i, e) in line.iter_mut().enumerate() {
+				*e = self.data[start + i * step];
+			}
+
+			let mut sum: f32 = line.iter().take(radius).sum();
+			for i in 0..len {
+				if i + radius < len {sum += line[i + radius]};
+				if i > radius {sum -= line[i - radius - 1]};
+				self.data[start + i * step] = (sum / size).max(0.0);
+			}
+		}
+	}
+}
+
+
+// sizes of the n box blurs for the gaussian blur, http://blog.ivank.net/fastest-gaussian-blur.html
+fn get_box_sizes(sigma: f32, n: usize) -> Vec<usize> {
+	let n_f = n as f32;
+	let ideal = (12.0 * sigma * sigma / n_f + 1.0).sqrt();
+	let mut lower = ideal.floor() as i32;
+	if lower % 2 == 0 {lower -= 1};
+	let lower_f = lower as f32;
+	let upper = lower + 2;
+
+	let m = ((12.0 * sigma * sigma - n_f * lower_f * lower_f - 4.0 * n_f * lower_f - 3.0 * n_f) / (-4.0 * lower_f - 4.0)).round() as usize;
+
+	(0..n)
+		.map(|i| if i < m {lower.max(1) as usize} else {upper as usize})
+		.collect()
+}
@@ -0,0 +1,662 @@
+use std::collections::HashMap;
+
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct FontFeature {
+	pub tag: [u8; 4],
+	pub enabled: bool,
+}
+
+impl FontFeature {
+
+	// "tnum", "+smcp", "-liga", "ss01=0"
+	pub fn parse(val: &str) -> Option<Self> {
+		let val = val.trim();
+		let (val, mut enabled) = if let Some(v) = val.strip_prefix('-') {
+			(v, false)
+		} else if let Some(v) = val.strip_prefix('+') {
+			(v, true)
+		} else {
+			(val, true)
+		};
+
+		let mut parts = val.splitn(2, '=');
+		let name = parts.next().unwrap_or("").trim();
+		if let Some(v) = parts.next() {
+			match v.trim() {
+				"0"|"off"|"false" => { enabled = false }
+				"1"|"on"|"true" => {}
+				_ => { return None }
+			}
+		}
+
+		let bytes = name.as_bytes();
+		if bytes.is_empty() || bytes.len() > 4 || !name.is_ascii() {return None};
+		let mut tag = [b' '; 4];
+		tag[..bytes.len()].copy_from_slice(bytes);
+
+		Some(Self { tag, enabled })
+	}
+
+	pub fn parse_list(val: &str) -> Vec<Self> {
+		val
+			.split(',')
+			.filter(|e| !e.trim().is_empty())
+			.filter_map(|e| {
+				let res = Self::parse(e);
+				if res.is_none() {
+					println!("wrong font feature: '{}'", e.trim());
+				}
+				res
+			})
+			.collect()
+	}
+}
+
+
+// features on until font-features turns them off, like "-liga"
+const DEFAULT_FEATURES: &[&[u8; 4]] = &[b"kern", b"liga", b"clig"];
+
+
+type FeatureList = Vec<([u8; 4], Vec<u16>)>;
+
+
+#[derive(Debug)]
+enum Substitution {
+	Single(HashMap<u16, u16>),
+	Ligature(HashMap<u16, Vec<(Vec<u16>, u16)>>),
+}
+
+#[derive(Debug)]
+enum Positioning {
+	Pairs(HashMap<(u16, u16), i16>),
+	Classes {
+		coverage: HashMap<u16, u16>,
+		class_1: HashMap<u16, u16>,
+		class_2: HashMap<u16, u16>,
+		class_2_count: usize,
+		values: Vec<i16>,
+	},
+}
+
+
+#[derive(Debug, Default)]
+pub struct FontTables {
+	gsub_features: FeatureList,
+	gsub_lookups: Vec<Vec<Substitution>>,
+	gpos_features: FeatureList,
+	gpos_lookups: Vec<Vec<Positioning>>,
+	// font units, positions are the top of the line above the baseline
+	pub underline_position: i16,
+	pub underline_thickness: i16,
+	pub strikeout_position: i16,
+	pub strikeout_thickness: i16,
+}
+
+
+impl FontTables {
+
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn from_bytes(data: &[u8]) -> Self {
+		let mut tables = Self::new();
+
+		// glyph ranges of the layout tables are clamped to the glyphs of the font
+		let glyph_count = find_table(data, b"maxp")
+			.and_then(|maxp| read_u16(maxp, 4))
+			.map_or(0x10000, u32::from);
+
+		if let Some(post) = find_table(data, b"post") {
+			tables.underline_position = read_i16(post, 8).unwrap_or(0);
+			tables.underline_thickness = read_i16(post, 10).unwrap_or(0);
+		}
+
+		if let Some(os2) = find_table(data, b"OS/2") {
+			tables.strikeout_thickness = read_i16(os2, 26).unwrap_or(0);
+			tables.strikeout_position = read_i16(os2, 28).unwrap_or(0);
+		}
+
+		if let Some(gsub) = find_table(data, b"GSUB") {
+			if let Some((features, lookups)) = parse_layout_table(gsub, 7, |t, d| parse_substitution(t, d, glyph_count)) {
+				tables.gsub_features = features;
+				tables.gsub_lookups = lookups;
+			}
+		}
+
+		if let Some(gpos) = find_table(data, b"GPOS") {
+			if let Some((features, lookups)) = parse_layout_table(gpos, 9, |t, d| parse_positioning(t, d, glyph_count)) {
+				tables.gpos_features = features;
+				tables.gpos_lookups = lookups;
+			}
+		}
+
+		tables
+	}
+
+
+	// glyphs are the source text and the glyph id, a ligature joins the text of its components
+	pub fn substitute(&self, glyphs: &mut Vec<(String, u16)>, features: &[FontFeature]) {
+		for lookup in enabled_lookups(&self.gsub_features, features) {
+			let lookup = match self.gsub_lookups.get(lookup as usize) {
+				Some(l) => l,
+				None => continue,
+			};
+
+			let mut i = 0;
+			while i < glyphs.len() {
+				for subtable in lookup.iter() {
+					if apply_substitution(subtable, glyphs, i) {break};
+				}
+				i += 1;
+			}
+		}
+	}
+
+	// None if the font has no GPOS kerning, so the caller can fall back to the 'kern' table
+	pub fn kerning(&self, features: &[FontFeature], left: u16, right: u16) -> Option<i16> {
+		if !is_enabled(b"kern", features) {return Some(0)};
+
+		let lookups = enabled_lookups(&self.gpos_features, features);
+		let mut has_kern = false;
+		let mut res: i16 = 0;
+
+		for lookup in lookups {
+			let lookup = match self.gpos_lookups.get(lookup as usize) {
+				Some(l) => l,
+				None => continue,
+			};
+			has_kern = true;
+
+			for subtable in lookup.iter() {
+				if let Some(v) = pair_adjustment(subtable, left, right) {
+					res = res.saturating_add(v);
+					break;
+				}
+			}
+		}
+
+		if has_kern {Some(res)} else {None}
+	}
+}
+
+
+fn is_enabled(tag: &[u8; 4], features: &[FontFeature]) -> bool {
+	match features.iter().rev().find(|f| f.tag == *tag) {
+		Some(f) => f.enabled,
+		None => DEFAULT_FEATURES.contains(&tag),
+	}
+}
+
+fn enabled_lookups(table_features: &[([u8; 4], Vec<u16>)], features: &[FontFeature]) -> Vec<u16> {
+	let mut lookups: Vec<u16> = table_features
+		.iter()
+		.filter(|(tag, _)| is_enabled(tag, features))
+		.flat_map(|(_, l)| l.iter().cloned())
+		.collect();
+	lookups.sort();
+	lookups.dedup();
+	lookups
+}
+
+
+fn apply_substitution(subtable: &Substitution, glyphs: &mut Vec<(String, u16)>, i: usize) -> bool {
+	match subtable {
+		Substitution::Single(map) => {
+			if let Some(g) = map.get(&glyphs[i].1) {
+				glyphs[i].1 = *g;
+				return true;
+			}
+			false
+		}
+		Substitution::Ligature(map) => {
+			let ligatures = match map.get(&glyphs[i].1) {
+				Some(l) => l,
+				None => return false,
+			};
+			for (components, ligature) in ligatures {
+				let end = i + 1 + components.len();
+				if end > glyphs.len() {continue};
+				if glyphs[i+1..end].iter().map(|e| e.1).eq(components.iter().cloned()) {
+					let text: String = glyphs.drain(i+1..end).map(|e| e.0).collect();
+					glyphs[i].0.push_str(&text);
+					glyphs[i].1 = *ligature;
+					return true;
+				}
+			}
+			false
+		}
+	}
+}
+
+fn pair_adjustment(subtable: &Positioning, left: u16, right: u16) -> Option<i16> {
+	match subtable {
+		Positioning::Pairs(map) => map.get(&(left, right)).cloned(),
+		Positioning::Classes { coverage, class_1, class_2, class_2_count, values } => {
+			if !coverage.contains_key(&left) {return None};
+			let c1 = *class_1.get(&left).unwrap_or(&0) as usize;
+			let c2 = *class_2.get(&right).unwrap_or(&0) as usize;
+			values.get(c1 * class_2_count + c2).cloned()
+		}
+	}
+}
+
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+	let b = data.get(offset..offset+2)?;
+	Some(((b[0] as u16) << 8) | b[1] as u16)
+}
+
+fn read_i16(data: &[u8], offset: usize) -> Option<i16> {
+	read_u16(data, offset).map(|v| v as i16)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+	Some(((read_u16(data, offset)? as u32) << 16) | read_u16(data, offset+2)? as u32)
+}
+
+fn read_tag(data: &[u8], offset: usize) -> Option<[u8; 4]> {
+	let b = data.get(offset..offset+4)?;
+	Some([b[0], b[1], b[2], b[3]])
+}
+
+fn find_table<'a>(data: &'a [u8], tag: &[u8; 4]) -> Option<&'a [u8]> {
+	let count = read_u16(data, 4)? as usize;
+	for i in 0..count {
+		let record = 12 + i * 16;
+		if read_tag(data, record)? == *tag {
+			let offset = read_u32(data, record + 8)? as usize;
+			let length = read_u32(data, record + 12)? as usize;
+			return data.get(offset..offset+length);
+		}
+	}
+	None
+}
+
+
+// glyph -> coverage index
+fn parse_coverage(data: &[u8], glyph_count: u32) -> Option<HashMap<u16, u16>> {
+	let mut res = HashMap::new();
+	match read_u16(data, 0)? {
+		1 => {
+			let count = read_u16(data, 2)? as usize;
+			for i in 0..count {
+				let glyph = read_u16(data, 4 + i * 2)?;
+				if u32::from(glyph) < glyph_count {
+					res.insert(glyph, i as u16);
+				}
+			}
+		}
+		2 => {
+			let count = read_u16(data, 2)? as usize;
+			for i in 0..count {
+				let record = 4 + i * 6;
+				let start = read_u16(data, record)?;
+				let end = read_u16(data, record + 2)?;
+				let index = read_u16(data, record + 4)?;
+				// ranges of a broken font are skipped
+				if end < start || u32::from(start) >= glyph_count || index.checked_add(end - start).is_none() {continue};
+				for g in start..=end.min((glyph_count - 1) as u16) {
+					res.insert(g, index + (g - start));
+				}
+			}
+		}
+		_ => return None,
+	}
+	Some(res)
+}
+
+fn parse_class_def(data: &[u8], glyph_count: u32) -> Option<HashMap<u16, u16>> {
+	let mut res = HashMap::new();
+	match read_u16(data, 0)? {
+		1 => {
+			let start = read_u16(data, 2)?;
+			let count = read_u16(data, 4)?;
+			for i in 0..count {
+				let glyph = match start.checked_add(i) {
+					Some(g) if u32::from(g) < glyph_count => g,
+					_ => break,
+				};
+				res.insert(glyph, read_u16(data, 6 + i as usize * 2)?);
+			}
+		}
+		2 => {
+			let count = read_u16(data, 2)? as usize;
+			for i in 0..count {
+				let record = 4 + i * 6;
+				let start = read_u16(data, record)?;
+				let end = read_u16(data, record + 2)?;
+				let class = read_u16(data, record + 4)?;
+				if end < start || u32::from(start) >= glyph_count {continue};
+				for g in start..=end.min((glyph_count - 1) as u16) {
+					res.insert(g, class);
+				}
+			}
+		}
+		_ => return None,
+	}
+	Some(res)
+}
+
+
+fn parse_layout_table<T, F>(data: &[u8], extension_type: u16, parse_subtable: F)
+	-> Option<(FeatureList, Vec<Vec<T>>)>
+	where F: Fn(u16, &[u8]) -> Option<T>
+{
+	let feature_list = data.get(read_u16(data, 6)? as usize..)?;
+	let lookup_list = data.get(read_u16(data, 8)? as usize..)?;
+
+	let mut features = Vec::new();
+	let feature_count = read_u16(feature_list, 0)? as usize;
+	for i in 0..feature_count {
+		let record = 2 + i * 6;
+		let tag = read_tag(feature_list, record)?;
+		let feature = feature_list.get(read_u16(feature_list, record + 4)? as usize..)?;
+		let count = read_u16(feature, 2)? as usize;
+		let mut lookups = Vec::with_capacity(count);
+		for j in 0..count {
+			lookups.push(read_u16(feature, 4 + j * 2)?);
+		}
+		features.push((tag, lookups));
+	}
+
+	let mut lookups = Vec::new();
+	let lookup_count = read_u16(lookup_list, 0)? as usize;
+	for i in 0..lookup_count {
+		let lookup = lookup_list.get(read_u16(lookup_list, 2 + i * 2)? as usize..)?;
+		let lookup_type = read_u16(lookup, 0)?;
+		let count = read_u16(lookup, 4)? as usize;
+		let mut subtables = Vec::new();
+		for j in 0..count {
+			let mut subtable = lookup.get(read_u16(lookup, 6 + j * 2)? as usize..)?;
+			let mut subtable_type = lookup_type;
+			if subtable_type == extension_type {
+				subtable_type = read_u16(subtable, 2)?;
+				subtable = subtable.get(read_u32(subtable, 4)? as usize..)?;
+			}
+			if let Some(s) = parse_subtable(subtable_type, subtable) {
+				subtables.push(s);
+			}
+		}
+		lookups.push(subtables);
+	}
+
+	Some((features, lookups))
+}
+
+
+fn parse_substitution(lookup_type: u16, data: &[u8], glyph_count: u32) -> Option<Substitution> {
+	let format = read_u16(data, 0)?;
+	let coverage = parse_coverage(data.get(read_u16(data, 2)? as usize..)?, glyph_count)?;
+
+	match (lookup_type, format) {
+		(1, 1) => {
+			let delta = read_i16(data, 4)?;
+			Some(Substitution::Single(coverage
+				.keys()
+				.map(|g| (*g, (*g as i32 + delta as i32) as u16))
+				.collect()))
+		}
+		(1, 2) => {
+			let mut map = HashMap::new();
+			for (g, i) in coverage {
+				map.insert(g, read_u16(data, 6 + i as usize * 2)?);
+			}
+			Some(Substitution::Single(map))
+		}
+		// alternate substitution: take the first alternate
+		(3, 1) => {
+			let mut map = HashMap::new();
+			for (g, i) in coverage {
+				let set = data.get(read_u16(data, 6 + i as usize * 2)? as usize..)?;
+				if read_u16(set, 0)? > 0 {
+					map.insert(g, read_u16(set, 2)?);
+				}
+			}
+			Some(Substitution::Single(map))
+		}
+		(4, 1) => {
+			let mut map = HashMap::new();
+			for (g, i) in coverage {
+				let set = data.get(read_u16(data, 6 + i as usize * 2)? as usize..)?;
+				let count = read_u16(set, 0)? as usize;
+				let mut ligatures = Vec::with_capacity(count);
+				for j in 0..count {
+					let ligature = set.get(read_u16(set, 2 + j * 2)? as usize..)?;
+					let glyph = read_u16(ligature, 0)?;
+					let components_count = read_u16(ligature, 2)? as usize;
+					let mut components = Vec::new();
+					for k in 1..components_count {
+						components.push(read_u16(ligature, 2 + k * 2)?);
+					}
+					ligatures.push((components, glyph));
+				}
+				map.insert(g, ligatures);
+			}
+			Some(Substitution::Ligature(map))
+		}
+		_ => None,
+	}
+}
+
+
+fn value_record_size(format: u16) -> usize {
+	format.count_ones() as usize * 2
+}
+
+fn x_advance(data: &[u8], offset: usize, format: u16) -> Option<i16> {
+	if format & 0x0004 == 0 {return Some(0)};
+	let skip = (format & 0x0003).count_ones() as usize * 2;
+	read_i16(data, offset + skip)
+}
+
+fn parse_positioning(lookup_type: u16, data: &[u8], glyph_count: u32) -> Option<Positioning> {
+	if lookup_type != 2 {return None};
+
+	let format = read_u16(data, 0)?;
+	let coverage = parse_coverage(data.get(read_u16(data, 2)? as usize..)?, glyph_count)?;
+	let format_1 = read_u16(data, 4)?;
+	let format_2 = read_u16(data, 6)?;
+	let record_size = value_record_size(format_1) + value_record_size(format_2);
+
+	match format {
+		1 => {
+			let mut map = HashMap::new();
+			for (g, i) in coverage {
+				let set = data.get(read_u16(data, 10 + i as usize * 2)? as usize..)?;
+				let count = read_u16(set, 0)? as usize;
+				for j in 0..count {
+					let record = 2 + j * (2 + record_size);
+					let second = read_u16(set, record)?;
+					map.insert((g, second), x_advance(set, record + 2, format_1)?);
+				}
+			}
+			Some(Positioning::Pairs(map))
+		}
+		2 => {
+			let class_1 = parse_class_def(data.get(read_u16(data, 8)? as usize..)?, glyph_count)?;
+			let class_2 = parse_class_def(data.get(read_u16(data, 10)? as usize..)?, glyph_count)?;
+			let class_1_count = read_u16(data, 12)? as usize;
+			let class_2_count = read_u16(data, 14)? as usize;
+			// the class counts come from the font, the records have to be in the table
+			let count = class_1_count * class_2_count;
+			if record_size == 0 || 16 + count * record_size > data.len() {return None};
+			let mut values = Vec::with_capacity(count);
+			for i in 0..count {
+				values.push(x_advance(data, 16 + i * record_size, format_1)?);
+			}
+			Some(Positioning::Classes { coverage, class_1, class_2, class_2_count, values })
+		}
+		_ => None,
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn bytes(words: &[u16]) -> Vec<u8> {
+		words.iter().flat_map(|w| w.to_be_bytes().to_vec()).collect()
+	}
+
+	fn tag(tag: &[u8; 4]) -> [u16; 2] {
+		[u16::from_be_bytes([tag[0], tag[1]]), u16::from_be_bytes([tag[2], tag[3]])]
+	}
+
+	// sfnt header with the table records, checksums are not read
+	fn font(tables: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+		let mut data = bytes(&[0x0001, 0x0000, tables.len() as u16, 0, 0, 0]);
+		let mut offset = 12 + tables.len() * 16;
+		for (t, table) in tables {
+			data.extend(bytes(&tag(t)));
+			data.extend(bytes(&[0, 0, 0, offset as u16, 0, table.len() as u16]));
+			offset += table.len();
+		}
+		for (_, table) in tables {
+			data.extend(table);
+		}
+		data
+	}
+
+	// one feature with one lookup with one subtable at 36
+	fn layout_table(feature: &[u8; 4], lookup_type: u16, subtable: &[u16]) -> Vec<u8> {
+		let t = tag(feature);
+		let mut words = vec![
+			1, 0, 0, 10, 24,
+			// feature list
+			1, t[0], t[1], 8,
+			0, 1, 0,
+			// lookup list
+			1, 4,
+			lookup_type, 0, 1, 8,
+		];
+		words.extend(subtable);
+		bytes(&words)
+	}
+
+	// f i -> fi
+	fn gsub_ligature() -> Vec<u8> {
+		layout_table(b"liga", 4, &[
+			1, 8, 1, 14,
+			// coverage
+			1, 1, 10,
+			// ligature set
+			1, 4,
+			99, 2, 11,
+		])
+	}
+
+	// A V kerned by -50
+	fn gpos_pair() -> Vec<u8> {
+		layout_table(b"kern", 2, &[
+			1, 12, 0x0004, 0, 1, 18,
+			// coverage
+			1, 1, 10,
+			// pair set
+			1, 11, (-50i16) as u16,
+		])
+	}
+
+	#[test]
+	fn parse_feature() {
+		assert_eq!(FontFeature::parse("tnum"), Some(FontFeature { tag: *b"tnum", enabled: true }));
+		assert_eq!(FontFeature::parse("-liga"), Some(FontFeature { tag: *b"liga", enabled: false }));
+		assert_eq!(FontFeature::parse("ss01=0"), Some(FontFeature { tag: *b"ss01", enabled: false }));
+		assert_eq!(FontFeature::parse("cv"), Some(FontFeature { tag: *b"cv  ", enabled: true }));
+		assert_eq!(FontFeature::parse("toolong"), None);
+		assert_eq!(FontFeature::parse_list("smcp, -kern,,bad=2").len(), 2);
+	}
+
+	#[test]
+	fn coverage_ranges() {
+		let coverage = parse_coverage(&bytes(&[2, 2, 5, 7, 0, 20, 21, 3]), 0x10000).unwrap();
+		assert_eq!(coverage.get(&6), Some(&1));
+		assert_eq!(coverage.get(&21), Some(&4));
+		assert_eq!(coverage.len(), 5);
+	}
+
+	#[test]
+	fn coverage_broken_ranges() {
+		// end before start and an index past u16::MAX are skipped
+		let coverage = parse_coverage(&bytes(&[2, 3, 9, 4, 0, 10, 20, 65530, 30, 30, 7]), 0x10000).unwrap();
+		assert_eq!(coverage.len(), 1);
+		assert_eq!(coverage.get(&30), Some(&7));
+	}
+
+	#[test]
+	fn class_def_overflow() {
+		let classes = parse_class_def(&bytes(&[1, 65534, 4, 1, 2, 3, 4]), 0x10000).unwrap();
+		assert_eq!(classes.len(), 2);
+		assert_eq!(classes.get(&65535), Some(&2));
+
+		let classes = parse_class_def(&bytes(&[2, 1, 65530, 65535, 3]), 0x10000).unwrap();
+		assert_eq!(classes.len(), 6);
+	}
+
+	#[test]
+	fn ranges_past_glyph_count() {
+		let coverage = parse_coverage(&bytes(&[2, 2, 0, 65535, 0, 20, 30, 0]), 10).unwrap();
+		assert_eq!(coverage.len(), 10);
+		let coverage = parse_coverage(&bytes(&[1, 2, 3, 12]), 10).unwrap();
+		assert_eq!(coverage.len(), 1);
+
+		let classes = parse_class_def(&bytes(&[2, 1, 5, 65535, 1]), 10).unwrap();
+		assert_eq!(classes.len(), 5);
+		let classes = parse_class_def(&bytes(&[1, 8, 4, 1, 2, 3, 4]), 10).unwrap();
+		assert_eq!(classes.len(), 2);
+
+		// the glyph count comes from maxp
+		let maxp = bytes(&[1, 0, 11]);
+		let tables = FontTables::from_bytes(&font(&[(b"maxp", maxp.clone()), (b"GPOS", gpos_pair())]));
+		assert_eq!(tables.kerning(&[], 10, 11), Some(-50));
+		let maxp = bytes(&[1, 0, 10]);
+		let tables = FontTables::from_bytes(&font(&[(b"maxp", maxp), (b"GPOS", gpos_pair())]));
+		assert_eq!(tables.kerning(&[], 10, 11), Some(0));
+	}
+
+	#[test]
+	fn class_pairs_past_table() {
+		// class counts of 65535 x 65535 with no records
+		let subtable = bytes(&[2, 18, 0x0004, 0, 24, 24, 65535, 65535, 0, 1, 1, 10, 1, 10, 0, 0]);
+		assert!(parse_positioning(2, &subtable, 0x10000).is_none());
+		// no value records at all
+		let subtable = bytes(&[2, 18, 0, 0, 24, 24, 65535, 65535, 0, 1, 1, 10, 1, 10, 0, 0]);
+		assert!(parse_positioning(2, &subtable, 0x10000).is_none());
+	}
+
+	#[test]
+	fn ligatures_by_default() {
+		let tables = FontTables::from_bytes(&font(&[(b"GSUB", gsub_ligature())]));
+		let text: Vec<(String, u16)> = vec![("f".into(), 10), ("i".into(), 11), ("x".into(), 12)];
+
+		let mut glyphs = text.clone();
+		tables.substitute(&mut glyphs, &[]);
+		assert_eq!(glyphs, vec![("fi".to_string(), 99), ("x".to_string(), 12)]);
+
+		let mut glyphs = text.clone();
+		tables.substitute(&mut glyphs, &FontFeature::parse_list("-liga"));
+		assert_eq!(glyphs, text);
+	}
+
+	#[test]
+	fn pair_kerning() {
+		let tables = FontTables::from_bytes(&font(&[(b"GPOS", gpos_pair())]));
+		assert_eq!(tables.kerning(&[], 10, 11), Some(-50));
+		assert_eq!(tables.kerning(&[], 10, 12), Some(0));
+		assert_eq!(tables.kerning(&FontFeature::parse_list("-kern"), 10, 11), Some(0));
+
+		// no GPOS, the caller uses the kern table
+		let tables = FontTables::from_bytes(&font(&[(b"GSUB", gsub_ligature())]));
+		assert_eq!(tables.kerning(&[], 10, 11), None);
+	}
+
+	#[test]
+	fn truncated_font() {
+		let mut data = font(&[(b"GSUB", gsub_ligature()), (b"GPOS", gpos_pair())]);
+		data.truncate(60);
+		let tables = FontTables::from_bytes(&data);
+		assert_eq!(tables.kerning(&[], 10, 11), None);
+	}
+}
@@ -2,285 +2,2058 @@ extern crate rusttype;
 
 use std::path::PathBuf;
 use std::char;
-use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
 use std::fs::{File};
 use std::io::Read;
-use self::rusttype::{Scale, point, Rect, Font};
-use chunk::{FormatBlock, Layout, TextAlignHorizontal};
-use img_buffer::{ImgBufferTrait};
+use self::rusttype::{Scale, point, vector, Point, Vector, Rect, Font, GlyphId, ScaledGlyph, Contour, Segment};
+use chunk::{FormatBlock, FormatChunks, RenderBlock, RenderChunk, DecorationMetrics, DecorationStyle, LineJoin, TextShadow, Paint, PaintArea, InlineImage, ImageAlign, OBJECT_REPLACEMENT, Paragraph, Line, Layout, TextAlignHorizontal, TabAlign, TabStop, BlockFit, Overflow, LineBreaking, Metrics, BlockMetrics, LineMetrics};
+use img_buffer::{ImgBuffer, ImgBufferTrait, ColorRGBA, BlendMode};
+use mask::Mask;
+use opentype::FontTables;
 
 
 pub struct TextRenderer {}
 
+enum Item<'a> {
+	Glyph {
+		glyph: ScaledGlyph<'a>,
+		chunk: RenderChunk,
+		symbol: char,
+		width: f32,
+		height: f32,
+		ascent: f32,
+		descent: f32,
+	},
+	// rendered as the hyphen glyph, only at the end of a line
+	SoftHyphen {
+		glyph: ScaledGlyph<'a>,
+		chunk: RenderChunk,
+		width: f32,
+		height: f32,
+		ascent: f32,
+		descent: f32,
+	},
+	LineBreak,
+	// the advance depends on the position in the line, glyph is the space
+	Tab {
+		glyph: ScaledGlyph<'a>,
+		chunk: RenderChunk,
+		height: f32,
+		ascent: f32,
+		descent: f32,
+	},
+	// start of a <p>, or of the text after it
+	Paragraph(Paragraph),
+	// list marker of the paragraph, with a space after it
+	Marker {
+		glyphs: Vec<(ScaledGlyph<'a>, RenderChunk, char, f32)>,
+		height: f32,
+		ascent: f32,
+		descent: f32,
+	},
+}
+
+
+type LineRange = (usize, usize, usize, bool);
+
+
 impl TextRenderer {
 	pub fn new () -> Self {
 		Self {}
 	}
 
 
-	pub fn load_fonts <'a>(data: Vec<(String, PathBuf)>) -> Vec<(String, Font<'a>)> {
+	pub fn load_fonts <'a>(data: Vec<(String, PathBuf)>) -> Vec<(String, Font<'a>, FontTables)> {
 		let mut result = Vec::with_capacity(data.len());
 		for (name, path) in data {
 			let mut f = File::open(path).expect("wrong font path");
 			let mut buffer = Vec::new();
 			f.read_to_end(&mut buffer).expect("cant read from font file");
+			let tables = FontTables::from_bytes(&buffer);
 			let font = Font::from_bytes(buffer).expect("Error constructing Font");
-			result.push((name, font));
+			result.push((name, font, tables));
 		}
 		result
 	}
 
 
-	fn find_font<'a>(name: &Option<String>, fonts: &'a[(String, Font<'a>)] ) -> (Option<std::string::String>, &'a rusttype::Font<'a>) {
+	fn find_font<'a>(name: &Option<String>, fonts: &'a[(String, Font<'a>, FontTables)] ) -> (Option<std::string::String>, &'a rusttype::Font<'a>, &'a FontTables) {
 		match name {
-			None => {(Some(fonts[0].0.clone()), &fonts[0].1)}
+			None => {(Some(fonts[0].0.clone()), &fonts[0].1, &fonts[0].2)}
 			Some(font_name) => {
 				if let Some(font) = fonts
 					.iter()
-					.find(|(e_name, _)| e_name == font_name )
+					.find(|(e_name, ..)| e_name == font_name )
 					{
-						return (Some(font.0.clone()), &font.1);
+						return (Some(font.0.clone()), &font.1, &font.2);
 					}
-				(Some(fonts[0].0.clone()), &fonts[0].1)
+				(Some(fonts[0].0.clone()), &fonts[0].1, &fonts[0].2)
 			}
 		}
 	}
 
 
-	pub fn format<'a>(format_blocks: Vec<FormatBlock>, dpi_factor: f32, fonts: &'a[(String, Font<'a>)]) -> Layout<'a> {
+	pub fn format<'a>(mut format_blocks: Vec<FormatBlock>, dpi_factor: f32, fonts: &'a[(String, Font<'a>, FontTables)]) -> Layout<'a> {
 
 		let mut layout = Layout {
 			blocks: Vec::with_capacity(format_blocks.len()),
+			truncated: false,
 			width:0.0,
 			height:0.0,
 			x:0.0,
 			y:0.0,
 		};
 
-		let (mut current_font_name, mut font) = Self::find_font(&None, fonts);
-		let mut scale = Scale::uniform(0.0);
+		let count = format_blocks.len();
+		let next: Vec<Option<usize>> = format_blocks
+			.iter()
+			.map(|block| block.next.as_ref().and_then(|next| {
+				format_blocks
+					.iter()
+					.position(|e| e.id.as_ref() == Some(next))
+			}))
+			.collect();
+
+		let mut is_continuation = vec![false; count];
+		for (i, n) in next.iter().enumerate() {
+			if let Some(n) = *n {
+				if n != i {is_continuation[n] = true};
+			}
+		}
+
+		let mut render_blocks: Vec<Option<RenderBlock>> = (0..count).map(|_| None).collect();
+		let mut visited = vec![false; count];
 
-		for block in format_blocks {
+		// chains start at the blocks that continue no other one, then at the first block of a cycle
+		let starts: Vec<usize> = (0..count).filter(|i| !is_continuation[*i]).chain(0..count).collect();
 
-			let mut last_wight_space = None;
-			let mut line_width = 0.0;
-			let mut prev_glyph_id = None;
+		for i in starts {
+			if visited[i] {continue};
+			visited[i] = true;
+
+			if format_blocks[i].table.is_some() {
+				render_blocks[i] = Some(Self::format_table(&mut format_blocks[i], dpi_factor, fonts));
+				continue;
+			}
 
-			let mut render_block = block.to_render_block();
+			if next[i].is_none() {
+				let block = &mut format_blocks[i];
+				render_blocks[i] = Some(match block.fit {
+					BlockFit::None => Self::format_block(block, 1.0, dpi_factor, fonts),
+					_ => Self::fit_block(block, dpi_factor, fonts),
+				});
+				continue;
+			}
 
-			for (chunk, str_data) in block.chunk.iter() {
+			let mut chain = vec![i];
+			while let Some(n) = next[*chain.last().unwrap()] {
+				if visited[n] {break};
+				visited[n] = true;
+				chain.push(n);
+			}
 
-				if is_font_need_update(&chunk.font, &current_font_name) {
-					let (f,n) = Self::find_font(&chunk.font, fonts);
-					current_font_name = f;
-					font = n;
-					prev_glyph_id = None;
+			for frame in chain.iter().skip(1) {
+				let block = &format_blocks[*frame];
+				if block.chunk.iter().any(|(_, text)| !text.trim().is_empty()) {
+					println!("text of the continuation block '{}' is ignored", block.id.as_deref().unwrap_or(""));
+				}
+			}
+
+			let items = Self::shape_block(&format_blocks[i], 1.0, dpi_factor, fonts);
+			let mut pos = 0;
+			let mut line_offset = 0;
+			for (k, frame) in chain.iter().enumerate() {
+				let mut render_block = Self::fill_frame(&format_blocks[*frame], &items, &mut pos, k == chain.len()-1);
+				render_block.line_offset = line_offset;
+				line_offset += render_block.lines.len();
+				render_blocks[*frame] = Some(render_block);
+			}
+		}
+
+		let mut flow: HashMap<usize, f32> = HashMap::new();
+
+		for (mut block, render_block) in format_blocks.into_iter().zip(render_blocks) {
+			if block.flow {
+				if let Some(y) = flow.get(&block.flow_container) {
+					block.y = *y;
+				}
+				block.y += block.margin_top + block.padding + block.border_width;
+			}
+
+			let mut render_block = render_block.unwrap_or_else(|| block.to_render_block());
+			render_block.x = block.x;
+			render_block.y = block.y;
+
+			Self::apply_overflow(&block, &mut render_block);
+			layout.truncated |= render_block.truncated;
+
+			if block.flow {
+				let height = if block.height > 0.0 {block.height} else {render_block.height};
+				flow.insert(block.flow_container, block.y + height + block.padding + block.border_width + block.margin_bottom);
+			}
+
+			layout.blocks.push((block, render_block));
+		}
+
+		layout
+	}
+
+
+	// column widths come from the min and max content widths of the cells, like the auto table layout of html
+	fn format_table<'a>(block: &mut FormatBlock, dpi_factor: f32, fonts: &'a[(String, Font<'a>, FontTables)]) -> RenderBlock<'a> {
+		let mut render_block = block.to_render_block();
+		render_block.lines.clear();
+
+		// the cells draw the borders, the table only places them
+		let border = block.border_width;
+		let table_width = block.width;
+		block.border_width = 0.0;
+		block.padding = 0.0;
+
+		let table = block.table.as_mut().unwrap();
+		let count = table.get_columns_count();
+
+		let mut fixed_widths: Vec<Option<f32>> = vec![None; count];
+		let mut min_widths = vec![0.0f32; count];
+		let mut max_widths = vec![0.0f32; count];
+		for row in table.rows.iter_mut() {
+			for (i, cell) in row.iter_mut().enumerate() {
+				let padding = 2.0 * cell.padding;
+				if cell.width > 0.0 {
+					fixed_widths[i] = Some(fixed_widths[i].unwrap_or(0.0).max(cell.width + padding));
+				} else {
+					let (min, max) = Self::get_content_widths(cell, dpi_factor, fonts);
+					min_widths[i] = min_widths[i].max(min + padding);
+					max_widths[i] = max_widths[i].max(max + padding);
+				}
+			}
+		}
+
+		let spacing = border * (count + 1) as f32;
+		let auto_columns: Vec<usize> = (0..count).filter(|i| fixed_widths[*i].is_none()).collect();
+		let min_sum: f32 = auto_columns.iter().map(|i| min_widths[*i]).sum();
+		let max_sum: f32 = auto_columns.iter().map(|i| max_widths[*i]).sum();
+		let available = table_width - spacing - fixed_widths.iter().flatten().sum::<f32>();
+
+		let widths: Vec<f32> = (0..count)
+			.map(|i| match fixed_widths[i] {
+				Some(width) => width,
+				None if table_width == 0.0 => max_widths[i],
+				None if available <= min_sum => min_widths[i],
+				None if available >= max_sum && max_sum > 0.0 => max_widths[i] * available / max_sum,
+				None if available >= max_sum => available / auto_columns.len() as f32,
+				None => min_widths[i] + (max_widths[i] - min_widths[i]) * (available - min_sum) / (max_sum - min_sum),
+			})
+			.collect();
+
+		let mut y = border;
+		for row in table.rows.iter_mut() {
+			let mut x = border;
+			let mut cells = Vec::with_capacity(row.len());
+			for (cell, width) in row.iter_mut().zip(widths.iter()) {
+				cell.x = x + cell.padding;
+				cell.y = y + cell.padding;
+				cell.width = (width - 2.0 * cell.padding).max(1.0);
+				cells.push(match cell.fit {
+					BlockFit::None => Self::format_block(cell, 1.0, dpi_factor, fonts),
+					_ => Self::fit_block(cell, dpi_factor, fonts),
+				});
+				x += width + border;
+			}
+
+			let height = row
+				.iter()
+				.zip(cells.iter())
+				.map(|(cell, render_cell)| cell.height.max(render_cell.height) + 2.0 * cell.padding)
+				.fold(0.0, f32::max);
+
+			for (cell, render_cell) in row.iter_mut().zip(cells.iter_mut()) {
+				cell.height = height - 2.0 * cell.padding;
+				render_cell.x = cell.x;
+				render_cell.y = cell.y;
+				Self::apply_overflow(cell, render_cell);
+			}
+
+			render_block.cells.extend(cells);
+			y += height + border;
+		}
+
+		render_block.width = widths.iter().sum::<f32>() + spacing;
+		render_block.height = y;
+		render_block
+	}
+
+
+	// widths of the longest word and of the text without wrapping
+	// the longest word is measured from the items, a narrow block would split it into glyphs,
+	// it is rounded up to whole pixels to fit after the sums of the line breaking
+	fn get_content_widths(block: &mut FormatBlock, dpi_factor: f32, fonts: &[(String, Font, FontTables)]) -> (f32, f32) {
+		let items = Self::shape_block(block, 1.0, dpi_factor, fonts);
+		let min = Self::get_longest_word_width(&items).ceil();
+
+		let width = block.width;
+		block.width = 0.0;
+		let mut pos = 0;
+		let max = Self::fill_frame(block, &items, &mut pos, true).width;
+		block.width = width;
+
+		(min, max.max(min))
+	}
+
+	// with the margin of the paragraph, the indent is counted for every line
+	fn get_longest_word_width(items: &[Item]) -> f32 {
+		let is_word = |i: usize| matches!(&items[i], Item::Glyph { symbol, .. } if !is_can_line_break(*symbol));
+		let mut margin = 0.0;
+		let mut res: f32 = 0.0;
+		for (i, item) in items.iter().enumerate() {
+			match item {
+				Item::Paragraph(paragraph) => {
+					margin = paragraph.margin_left + paragraph.text_indent.max(0.0);
+				}
+				Item::Glyph { .. } if is_word(i) && (i == 0 || !is_word(i - 1)) => {
+					res = res.max(margin + Self::get_word_width(items, i));
 				}
+				_ => {}
+			}
+		}
+		res
+	}
+
 
-				scale = Scale::uniform(chunk.font_size as f32 * dpi_factor);
-				let v_metrics = font.v_metrics(scale);
+	fn format_block<'a>(block: &FormatBlock, font_scale: f32, dpi_factor: f32, fonts: &'a[(String, Font<'a>, FontTables)]) -> RenderBlock<'a> {
+		let items = Self::shape_block(block, font_scale, dpi_factor, fonts);
+		let mut pos = 0;
+		Self::fill_frame(block, &items, &mut pos, true)
+	}
+
+
+	fn shape_block<'a>(block: &FormatBlock, font_scale: f32, dpi_factor: f32, fonts: &'a[(String, Font<'a>, FontTables)]) -> Vec<Item<'a>> {
+
+		let (mut current_font_name, mut font, mut tables) = Self::find_font(&None, fonts);
+		let mut prev_glyph_id = None;
+		let mut paragraph = None;
+		let mut started_paragraphs = Vec::new();
+		let mut skip_whitespace = false;
+		let mut prev_symbol = None;
+		let mut items = Vec::new();
+
+		for (chunk, str_data) in block.chunk.iter() {
+
+			if is_font_need_update(&chunk.font, &current_font_name) {
+				let (f,n,t) = Self::find_font(&chunk.font, fonts);
+				current_font_name = f;
+				font = n;
+				tables = t;
+				prev_glyph_id = None;
+			}
+
+			let scale = Scale::uniform(chunk.get_scaled_font_size(font_scale) as f32 * dpi_factor);
+			let v_metrics = font.v_metrics(scale);
+			// pixels per font unit, rusttype scales the height from ascent to descent, not the em,
+			// so the GPOS values use the same factor as the glyph advances
+			let unscaled = font.v_metrics_unscaled();
+			let units = scale.y / (unscaled.ascent - unscaled.descent);
 
-				for symbol in str_data.chars() {
-					if is_line_break(symbol) {
-						render_block.get_last_line().force_break = true;
-						render_block.add_line();
-						prev_glyph_id = None;
-						line_width = 0.0;
-						continue;
+			let letter_spacing = chunk.letter_spacing.scale(font_scale).to_px(scale.y);
+			let word_spacing = chunk.word_spacing.scale(font_scale).to_px(scale.y);
+
+			let mut render_chunk = chunk.get_render_chunk();
+			render_chunk.decoration_metrics = get_decoration_metrics(tables, units, scale.y, v_metrics.ascent);
+			render_chunk.baseline_shift = chunk.get_baseline_shift() * font_scale * dpi_factor;
+			render_chunk.ascent = v_metrics.ascent + render_chunk.baseline_shift;
+			render_chunk.descent = v_metrics.descent + render_chunk.baseline_shift;
+
+			// shifted glyphs make the line taller, so they don't collide with the other lines
+			let shift = render_chunk.baseline_shift;
+			let height = (v_metrics.line_gap + v_metrics.ascent) * chunk.line_height + shift.abs();
+			let ascent = v_metrics.ascent + shift.max(0.0);
+			let descent = v_metrics.descent + shift.min(0.0);
+
+			if chunk.paragraph != paragraph {
+				// whitespace around paragraphs is collapsed, like in html
+				while items.last().is_some_and(is_whitespace_item) {
+					items.pop();
+				}
+				// as well as text outside of paragraphs with only whitespace
+				if paragraph.is_none() && matches!(items.last(), Some(Item::Paragraph(_))) {
+					items.pop();
+				}
+				paragraph = chunk.paragraph;
+				skip_whitespace = true;
+				prev_glyph_id = None;
+
+				let mut next = paragraph.map_or_else(Paragraph::new, |i| block.paragraphs[i].clone());
+				if let Some(i) = paragraph {
+					// text of a <li> after a nested list
+					if started_paragraphs.contains(&i) {
+						next.text_indent = 0.0;
+						next.spacing_before = 0.0;
+						next.marker = None;
 					}
+					started_paragraphs.push(i);
+				}
 
-					{
-						let line = render_block.get_last_line();
-						line.height = line.height.max( (v_metrics.line_gap + v_metrics.ascent) * chunk.line_height );
-						line.descent = line.descent.min( v_metrics.descent );
+				let marker = next.marker.as_ref().map(|marker| format!("{} ", marker));
+				items.push(Item::Paragraph(next));
+
+				if let Some(marker) = marker {
+					let glyphs = marker
+						.chars()
+						.map(|symbol| {
+							let symbol = [symbol, '•', '-']
+								.iter()
+								.cloned()
+								.find(|c| font.glyph(*c).id().0 != 0)
+								.unwrap_or(symbol);
+							let glyph = font.glyph(symbol).scaled(scale);
+							let width = glyph.h_metrics().advance_width;
+							(glyph, chunk.get_render_chunk(), symbol, width)
+						})
+						.collect();
+					items.push(Item::Marker {
+						glyphs,
+						height: (v_metrics.line_gap + v_metrics.ascent) * chunk.line_height,
+						ascent: v_metrics.ascent,
+						descent: v_metrics.descent,
+					});
+				}
+			}
+
+			if let Some(image) = &chunk.image {
+				// the middle of the image is at about the half of the x-height
+				let bottom = match image.align {
+					ImageAlign::Baseline => 0.0,
+					ImageAlign::Middle => v_metrics.ascent * 0.3 - image.height / 2.0,
+				} + shift;
+				let mut image_chunk = render_chunk.clone();
+				image_chunk.ascent = bottom + image.height;
+				image_chunk.descent = bottom;
+
+				items.push(Item::Glyph {
+					glyph: font.glyph(' ').scaled(scale),
+					chunk: image_chunk,
+					symbol: OBJECT_REPLACEMENT,
+					width: image.width + letter_spacing,
+					height: height.max(bottom + image.height - descent.min(bottom)),
+					ascent: ascent.max(bottom + image.height),
+					descent: descent.min(bottom),
+				});
+				skip_whitespace = false;
+				prev_glyph_id = None;
+				prev_symbol = Some(OBJECT_REPLACEMENT);
+				continue;
+			}
+
+			let text = chunk.text_transform.apply(str_data, chunk.lang.as_deref(), prev_symbol);
+			prev_symbol = str_data.chars().last().or(prev_symbol);
+
+			let mut shaped: Vec<(String, u16)> = text
+				.chars()
+				.map(|symbol| (symbol.to_string(), font.glyph(symbol).id().0 as u16))
+				.collect();
+			tables.substitute(&mut shaped, &chunk.font_features);
+
+			for (cluster, glyph_id) in shaped {
+				let mut cluster_chars = cluster.chars();
+				let symbol = match cluster_chars.next() {
+					Some(c) => c,
+					None => continue,
+				};
+				let is_ligature = cluster_chars.next().is_some();
+
+				if skip_whitespace {
+					if symbol.is_whitespace() {continue};
+					skip_whitespace = false;
+				}
+
+				if is_line_break(symbol) {
+					items.push(Item::LineBreak);
+					prev_glyph_id = None;
+					continue;
+				}
+
+				if symbol == SOFT_HYPHEN {
+					let glyph = font.glyph('-').scaled(scale);
+					items.push(Item::SoftHyphen {
+						width: glyph.h_metrics().advance_width,
+						glyph,
+						chunk: render_chunk.clone(),
+						height,
+						ascent,
+						descent,
+					});
+					continue;
+				}
+
+				if symbol == '\t' {
+					items.push(Item::Tab {
+						glyph: font.glyph(' ').scaled(scale),
+						chunk: render_chunk.clone(),
+						height,
+						ascent,
+						descent,
+					});
+					prev_glyph_id = None;
+					continue;
+				}
+
+				let glyph = font.glyph(GlyphId(glyph_id as u32)).scaled(scale);
+				let mut symbol_width = glyph.h_metrics().advance_width;
+
+				if let Some(id) = prev_glyph_id {
+					symbol_width += match tables.kerning(&chunk.font_features, id, glyph_id) {
+						Some(kerning) => kerning as f32 * units,
+						None => font.pair_kerning(scale, GlyphId(id as u32), glyph.id()),
+					};
+				}
+				prev_glyph_id = Some(glyph_id);
+
+				symbol_width += letter_spacing;
+				if symbol == ' ' || symbol == NO_BREAK_SPACE {
+					symbol_width += word_spacing;
+				}
+
+				let mut glyph_chunk = render_chunk.clone();
+				if is_ligature {
+					glyph_chunk.cluster = Some(cluster);
+				}
+
+				items.push(Item::Glyph {
+					glyph,
+					chunk: glyph_chunk,
+					symbol,
+					width: symbol_width,
+					height,
+					ascent,
+					descent,
+				});
+			}
+		}
+
+		items
+	}
+
+
+	// greedy line breaking, starting at the item `pos`, x is the start of the line in the column
+	fn next_line<'a>(block: &FormatBlock, items: &[Item<'a>], pos: &mut usize, x: f32, max_width: f32) -> Line<'a> {
+		let break_word = block.break_word;
+		let mut line = Line::new();
+		let mut metrics: Vec<(f32, f32, f32)> = Vec::new();
+		let mut line_width = 0.0;
+		let mut last_wight_space = None;
+
+		while *pos < items.len() {
+			match &items[*pos] {
+				Item::LineBreak => {
+					line.force_break = true;
+					*pos += 1;
+					break;
+				}
+				Item::Paragraph(_) | Item::Marker { .. } => {
+					line.force_break = true;
+					break;
+				}
+				Item::SoftHyphen { .. } => {
+					*pos += 1;
+				}
+				Item::Tab { glyph, chunk, height, ascent, descent } => {
+					let (width, stop) = Self::get_tab_width(block, items, *pos, x + line_width);
+
+					if max_width > 0.0 && line_width + width > max_width && !line.glyphs.is_empty() {
+						if let (false, Some((i, item))) = (break_word, last_wight_space) {
+							line.glyphs.truncate(i);
+							metrics.truncate(i);
+							*pos = item + 1;
+						}
+						break;
 					}
 
-					let base_glyph = font.glyph(symbol);
-					let mut glyph = base_glyph.scaled(scale);
-					let h_metrics = glyph.h_metrics();
-					let mut symbol_width = h_metrics.advance_width;
+					for (glyph, symbol, width) in Self::get_tab_glyphs(glyph, x + line_width, width, stop.leader) {
+						line.glyphs.push((glyph, chunk.clone(), symbol, width));
+						metrics.push((*height, *ascent, *descent));
+					}
+					line_width += width;
+					if !break_word {
+						last_wight_space = Some((line.glyphs.len(), *pos));
+					}
+					*pos += 1;
+				}
+				Item::Glyph { glyph, chunk, symbol, width, height, ascent, descent } => {
+					let is_space = is_can_line_break(*symbol);
 
-					if let Some(id) = prev_glyph_id {
-						symbol_width += font.pair_kerning(scale, id, glyph.id());
+					if max_width > 0.0 && line_width + width > max_width && !line.glyphs.is_empty() {
+						if is_space {
+							*pos += 1;
+						} else if let (false, Some((i, item))) = (break_word, last_wight_space) {
+							line.glyphs.truncate(i);
+							metrics.truncate(i);
+							*pos = item + 1;
+						} else {
+							line.split_word = !break_word;
+						}
+						break;
 					}
-					prev_glyph_id = Some(glyph.id());
-
-					if block.width == 0.0 {
-						render_block.get_last_line().glyphs.push((glyph, chunk.get_render_chunk(), symbol, symbol_width));
-						continue;
-					} else if line_width+symbol_width > block.width {
-						render_block.add_line();
-						prev_glyph_id = None;
-						line_width = 0.0;
-						
-						if is_can_line_break(symbol) {
-							last_wight_space = None;
+
+					if is_space {
+						if !break_word {
+							last_wight_space = Some((line.glyphs.len(), *pos));
+						} else if line_width == 0.0 {
+							*pos += 1;
 							continue;
 						}
+					}
 
-						if !block.break_word {
-							if let Some(i) = last_wight_space {
-								let mut vec = render_block.get_prev_line().glyphs
-									.splice(i.., None)
-									.skip(1)
-									.collect();
-								render_block.get_last_line().glyphs.append(&mut vec);
-								line_width = render_block.get_last_line().glyphs
-									.iter()
-									.map(|(..,sw)| sw )
-									.sum();
-								last_wight_space = None;
-							}
-						}
+					line.glyphs.push((glyph.clone(), chunk.clone(), *symbol, *width));
+					metrics.push((*height, *ascent, *descent));
+					line_width += width;
+					*pos += 1;
+				}
+			}
+		}
 
-						render_block.get_last_line().glyphs.push((glyph, chunk.get_render_chunk(), symbol, symbol_width));
-						line_width += symbol_width;
-					} else {
-						if is_can_line_break(symbol) {
-							if !block.break_word {
-								last_wight_space = Some(render_block.get_last_line().glyphs.len());
-							} else if line_width == 0.0 {continue;} 
-						}
+		// the end of the text ends the paragraph, lines continued in the next frame or page don't
+		if *pos == items.len() {
+			line.force_break = true;
+		}
+
+		// the tallest item above the baseline and the deepest one below it can differ, like images with the text
+		let mut above: f32 = 0.0;
+		for (height, ascent, descent) in metrics {
+			line.height = line.height.max(height);
+			line.ascent = line.ascent.max(ascent);
+			line.descent = line.descent.min(descent);
+			above = above.max(height + descent);
+		}
+		line.height = line.height.max(above - line.descent);
+
+		line
+	}
+
+
+	// width of the word at pos, up to the next break opportunity
+	fn get_word_width(items: &[Item], pos: usize) -> f32 {
+		items[pos..]
+			.iter()
+			.skip_while(|item| matches!(item, Item::Glyph { symbol, .. } if is_can_line_break(*symbol)))
+			.map_while(|item| match item {
+				Item::Glyph { symbol, width, .. } if !is_can_line_break(*symbol) => Some(*width),
+				_ => None,
+			})
+			.sum()
+	}
 
-						render_block.get_last_line().glyphs.push((glyph, chunk.get_render_chunk(), symbol, symbol_width));
-						line_width += symbol_width;
+
+	// advance of the tab at x, that aligns the text up to the next tab to the tab stop
+	fn get_tab_width(block: &FormatBlock, items: &[Item], pos: usize, x: f32) -> (f32, TabStop) {
+		let stop = block.get_tab_stop(x);
+
+		let mut segment = 0.0;
+		let mut decimal = None;
+		for item in items[pos+1..].iter() {
+			match item {
+				Item::Glyph { symbol, width, .. } => {
+					if *symbol == block.decimal_separator && decimal.is_none() {
+						decimal = Some(segment);
 					}
+					segment += width;
 				}
+				Item::SoftHyphen { .. } => {}
+				_ => break,
+			}
+		}
 
-				{
-					let mut prev_line_height = 0.0;
-					if let Some(h) = render_block.lines
-						.iter()
-						.find(|l| l.height > 0.0) {
-							prev_line_height = h.height;
-						}
-					render_block.lines
-						.iter_mut()
-						.for_each(|line| {
-							if line.height == 0.0 {line.height = prev_line_height} else {prev_line_height = line.height}
-						})
+		let width = match stop.align {
+			TabAlign::Left => stop.position - x,
+			TabAlign::Right => stop.position - x - segment,
+			TabAlign::Center => stop.position - x - segment / 2.0,
+			TabAlign::Decimal => stop.position - x - decimal.unwrap_or(segment),
+		};
+		(width.max(0.0), stop)
+	}
+
+
+	// blank advance of the tab, leaders are aligned to the grid of their width like in word processors
+	fn get_tab_glyphs<'a>(glyph: &ScaledGlyph<'a>, x: f32, width: f32, leader: Option<char>) -> Vec<(ScaledGlyph<'a>, char, f32)> {
+		let blank = vec![(glyph.clone(), '\t', width)];
+
+		let (symbol, leader) = match (leader, glyph.font()) {
+			(Some(symbol), Some(font)) => (symbol, font.glyph(symbol).scaled(glyph.scale())),
+			_ => return blank,
+		};
+		let leader_width = leader.h_metrics().advance_width;
+		if leader_width <= 0.0 {return blank};
+
+		let start = (x / leader_width).ceil() * leader_width;
+		let count = ((x + width - start) / leader_width).floor().max(0.0) as usize;
+		if count == 0 {return blank};
+
+		let mut glyphs = vec![(glyph.clone(), '\t', start - x)];
+		glyphs.extend((0..count).map(|_| (leader.clone(), symbol, leader_width)));
+		glyphs.push((glyph.clone(), '\t', x + width - start - count as f32 * leader_width));
+		glyphs
+	}
+
+
+	// tab widths depend on the position in the line, so these paragraphs are broken greedily
+	fn has_tabs(items: &[Item], pos: usize) -> bool {
+		items[pos..]
+			.iter()
+			.take_while(|item| !matches!(item, Item::LineBreak | Item::Paragraph(_) | Item::Marker { .. }))
+			.any(|item| matches!(item, Item::Tab { .. }))
+	}
+
+
+	// glyphs of items start..end, a soft hyphen is kept only at the end
+	fn line_from_range<'a>(items: &[Item<'a>], start: usize, end: usize) -> Line<'a> {
+		let mut line = Line::new();
+
+		for (i, item) in items.iter().enumerate().take(end).skip(start) {
+			let (glyph, chunk, symbol, width, height, ascent, descent) = match item {
+				Item::Glyph { glyph, chunk, symbol, width, height, ascent, descent } => (glyph, chunk, *symbol, width, height, ascent, descent),
+				Item::SoftHyphen { glyph, chunk, width, height, ascent, descent } if i == end-1 => (glyph, chunk, '-', width, height, ascent, descent),
+				_ => continue,
+			};
+			line.glyphs.push((glyph.clone(), chunk.clone(), symbol, *width));
+			line.height = line.height.max(*height);
+			line.ascent = line.ascent.max(*ascent);
+			line.descent = line.descent.min(*descent);
+		}
+
+		line
+	}
+
+
+	// total-fit (Knuth-Plass) line breaking of the paragraph starting at `start`, widths: (first line, other lines)
+	// returns (first item, end item, next line item, is forced break) for every line
+	fn optimal_breaks(block: &FormatBlock, items: &[Item], start: usize, widths: (f32, f32), text_align: TextAlignHorizontal) -> VecDeque<LineRange> {
+		let end = items[start..]
+			.iter()
+			.position(|item| matches!(item, Item::LineBreak | Item::Paragraph(_) | Item::Marker { .. }))
+			.map_or(items.len(), |i| start + i);
+		let is_line_break = matches!(items.get(end), Some(Item::LineBreak));
+
+		let is_space = |i: usize| matches!(&items[i], Item::Glyph { symbol, .. } if is_can_line_break(*symbol));
+		let shrink = if text_align == TextAlignHorizontal::Justify {block.space_shrink} else {0.0};
+
+		// prefix sums of width, stretch and shrink
+		let mut sums = vec![(0.0, 0.0, 0.0); end - start + 1];
+		for (i, item) in items.iter().enumerate().take(end).skip(start) {
+			let (w, y, z) = sums[i - start];
+			sums[i - start + 1] = match item {
+				Item::Glyph { width, .. } if is_space(i) => (w + width, y + width * block.space_stretch, z + width * shrink),
+				Item::Glyph { width, .. } => (w + width, y, z),
+				_ => (w, y, z),
+			};
+		}
+
+		// (break item, penalty, hyphen width)
+		let mut points: Vec<(usize, f32, f32)> = vec![(start, 0.0, 0.0)];
+		for (i, item) in items.iter().enumerate().take(end).skip(start) {
+			match item {
+				Item::Glyph { .. } if is_space(i) && i > start && !is_space(i-1) => points.push((i, 0.0, 0.0)),
+				Item::SoftHyphen { width, .. } => points.push((i, block.hyphen_penalty, *width)),
+				_ => {}
+			}
+		}
+		points.push((end, 0.0, 0.0));
+
+		let line_start = |p: usize| -> usize {
+			if p == 0 {return start};
+			let mut i = points[p].0 + 1;
+			while i < end && is_space(i) {i += 1}
+			i
+		};
+
+		let mut best: Vec<(f64, usize)> = vec![(f64::MAX, 0); points.len()];
+		best[0].0 = 0.0;
+
+		for j in 1..points.len() {
+			let (item, penalty, hyphen_width) = points[j];
+			let is_last = j == points.len()-1;
+
+			let mut is_nearest = true;
+
+			for i in (0..j).rev() {
+				if best[i].0 == f64::MAX {continue};
+
+				let from = line_start(i).min(item);
+				let (w0, y0, z0) = sums[from - start];
+				let (w1, y1, z1) = sums[item - start];
+				let natural = w1 - w0 + hyphen_width;
+				let width = if i == 0 {widths.0} else {widths.1};
+
+				let ratio = if natural <= width {
+					if y1 - y0 > 0.0 {(width - natural) / (y1 - y0)} else {f32::MAX}
+				} else if z1 - z0 > 0.0 {
+					(width - natural) / (z1 - z0)
+				} else {
+					f32::MIN
+				};
+
+				if ratio < -1.0 {
+					// overfull: a longer line can only be wider
+					if best[j].0 == f64::MAX && is_nearest {
+						best[j] = (best[i].0 + 1.0e10, i);
+					}
+					break;
 				}
 
-				{
-					let width = render_block.lines
-						.iter_mut()
-						.map(|line| -> f32 {
-							let width = line.glyphs
-								.iter()
-								.map(|e| e.3)
-								.sum();
-							line.width = width;
-							width
-						})
-						.max_by(|a, b| if a > b {Ordering::Greater} else {Ordering::Less})
-						.unwrap();
-					render_block.width = width;
-					let mut height = render_block.lines
-						.iter()
-						.map(|line| line.height )
-						.sum();
-					height += - render_block.get_last_line().descent;
-					render_block.height = height;
+				let badness = if is_last && natural <= width {0.0} else {(100.0 * (ratio as f64).abs().powi(3)).min(1.0e12)};
+				let demerits = (10.0 + badness).powi(2) + (penalty.max(0.0) as f64).powi(2);
+
+				if best[i].0 + demerits < best[j].0 {
+					best[j] = (best[i].0 + demerits, i);
 				}
+				is_nearest = false;
 			}
+		}
 
-			layout.blocks.push((block, render_block));
+		let mut lines = VecDeque::new();
+		let mut j = points.len()-1;
+		while j > 0 {
+			let i = best[j].1;
+			let item = points[j].0;
+			let (line_end, next) = if j == points.len()-1 {
+				(item, if is_line_break {item + 1} else {item})
+			} else if points[j].2 > 0.0 {
+				(item + 1, line_start(j))
+			} else {
+				(item, line_start(j))
+			};
+			lines.push_front((line_start(i).min(item), line_end, next, j == points.len()-1));
+			j = i;
 		}
 
-		layout
+		lines
 	}
 
 
-	pub fn render<T: ImgBufferTrait>( layout: &Layout, buffer: &mut T )
-	{
-		let mut caret = point(0.0, 0.0);
-		let buffer_width = buffer.width() as i32;
-		let buffer_height = buffer.height() as i32;
+	// lays out lines into the block, until its height (and columns) are full, unless it is the last frame
+	fn fill_frame<'a>(block: &FormatBlock, items: &[Item<'a>], pos: &mut usize, is_last: bool) -> RenderBlock<'a> {
+		let mut render_block = block.to_render_block();
+		render_block.lines.clear();
 
-		for ( f_block, r_block ) in layout.blocks.iter() {
-			let offset = point(f_block.x - layout.x, f_block.y - layout.y);
+		let column_width = block.get_column_width();
+		let column_height = if block.height > 0.0 {block.height} else {f32::MAX};
+		let mut column = 0;
+		let mut y = 0.0;
+		let mut prev_line_height = items[*pos..]
+			.iter()
+			.find_map(|item| if let Item::Glyph { height, .. } = item {Some(*height)} else {None})
+			.unwrap_or(0.0);
+		let mut planned: VecDeque<LineRange> = VecDeque::new();
 
-			caret.y = offset.y;
+		// a continuation frame starts inside the paragraph of the previous one
+		let mut paragraph = items[..*pos]
+			.iter()
+			.rev()
+			.find_map(|item| if let Item::Paragraph(paragraph) = item {Some(paragraph.clone())} else {None})
+			.unwrap_or_else(Paragraph::new);
+		let mut is_first_line = false;
+		let mut spacing: f32 = 0.0;
+		let mut marker = None;
+
+		while *pos < items.len() {
+			match &items[*pos] {
+				Item::Paragraph(next) => {
+					// like css margins, the spacing between paragraphs collapses
+					spacing = paragraph.spacing_after.max(next.spacing_before);
+					paragraph = next.clone();
+					is_first_line = true;
+					*pos += 1;
+					continue;
+				}
+				Item::Marker { glyphs, height, ascent, descent } => {
+					marker = Some((glyphs, *height, *ascent, *descent));
+					*pos += 1;
+					continue;
+				}
+				_ => {}
+			}
 
-			let lines_count = r_block.lines.len();
+			let start = *pos;
+			let column_x = column as f32 * (column_width + block.column_gap);
+			let text_align = paragraph.text_align.unwrap_or(block.text_align);
+			let rest_indent = paragraph.margin_left + (-paragraph.text_indent).max(0.0);
+			let indent = if is_first_line {paragraph.margin_left + paragraph.text_indent.max(0.0)} else {rest_indent};
 
-			for (i, line) in r_block.lines.iter().enumerate() {
-				caret.y += line.height + line.descent;
+			let is_planned = planned.front().map(|range| range.0) == Some(*pos);
+			// without a width the lines are not wrapped, like in next_line
+			let is_optimal = block.line_breaking == LineBreaking::Optimal && column_width > 0.0 && (is_planned || !Self::has_tabs(items, *pos));
 
-				let mut space_inc = 0.0; 
-				
-				match f_block.text_align {
-					TextAlignHorizontal::Right => {caret.x = offset.x + f_block.width - line.width;}
-					TextAlignHorizontal::Center => {caret.x = offset.x + ((f_block.width - line.width)/2.0);}
-					TextAlignHorizontal::Justify => {
-						caret.x = offset.x;
-						if !line.force_break && i != lines_count-1 {
-							let c = line.glyphs
-								.iter()
-								.filter( |(_,_,symbol,_)| *symbol == ' ')
-								.count();
-							space_inc = (f_block.width - line.width) / (c as f32);
-						}
-					} 
-					_ => {caret.x = offset.x;}
+			// the widths of the lines around exclusions depend on the height of every line,
+			// so they are broken greedily one by one, even with the optimal line breaking
+			// there is nothing to wrap around without a width
+			let mut line = if !block.exclusions.is_empty() && column_width > 0.0 {
+				Self::next_line_around(block, items, pos, column_x, y, prev_line_height, indent)
+			} else if is_optimal {
+				if !is_planned {
+					planned = Self::optimal_breaks(block, items, *pos, (column_width - indent, column_width - rest_indent), text_align);
 				}
+				let (start, end, next, is_forced) = planned.pop_front().unwrap();
+				*pos = next;
+				let mut line = Self::line_from_range(items, start, end);
+				line.force_break = is_forced;
+				line.x = column_x + indent;
+				line.max_width = column_width - indent;
+				line
+			} else {
+				let mut line = Self::next_line(block, items, pos, indent, column_width - indent);
+				line.x = column_x + indent;
+				line.max_width = column_width - indent;
+				line
+			};
+			if line.height == 0.0 {line.height = prev_line_height};
+			line.indent = indent;
+			line.text_align = paragraph.text_align;
 
-				for (scaled_glyph, chunk, symbol, symbol_width) in line.glyphs.iter() {
-					if *symbol == ' ' { caret.x += space_inc };
+			// no spacing at the top of a column
+			let line_spacing = if y > 0.0 {spacing} else {0.0};
 
-					let positioned_glyph = scaled_glyph.clone().positioned(caret);
+			let can_move = column < block.columns-1 || !is_last;
+			if can_move && y > 0.0 && y + line_spacing + line.height - line.descent > column_height {
+				*pos = start;
+				column += 1;
+				y = 0.0;
+				if column >= block.columns {break};
+				continue;
+			}
 
-					if let Some(bounding_box) = positioned_glyph.pixel_bounding_box() {
-						if can_draw(bounding_box, buffer_width, buffer_height) {
-							positioned_glyph.draw(|x, y, v| {
-								let x = bounding_box.min.x + (x as i32);
-								let y = bounding_box.min.y + (y as i32);
+			if let Some((glyphs, height, ascent, descent)) = marker.take() {
+				line.marker = glyphs.clone();
+				line.height = line.height.max(height);
+				line.ascent = line.ascent.max(ascent);
+				line.descent = line.descent.min(descent);
+			}
 
-								if x < 0 {return};
-								if y < 0 {return};
+			prev_line_height = line.height;
+			line.height += line_spacing;
+			line.y = y;
+			y += line.height;
+			render_block.lines.push(line);
+			is_first_line = false;
+			spacing = 0.0;
+		}
 
-								buffer.blend_pixel(x as usize, y as usize, &chunk.color, v);
-							});
-						}
-					}
-					caret.x += symbol_width;
+		let ends_with_break = matches!(items.last(), Some(Item::LineBreak));
+		if render_block.lines.is_empty() || (*pos == items.len() && ends_with_break) {
+			let mut line = Line::new();
+			line.x = column.min(block.columns-1) as f32 * (column_width + block.column_gap);
+			line.y = y;
+			line.max_width = column_width;
+			line.height = prev_line_height;
+			render_block.lines.push(line);
+		}
+
+		Self::position_lines(block, &mut render_block);
+		render_block.update_size();
+
+		render_block
+	}
+
+
+	// breaks the line into the widest free part of the column, retrying if the line is taller than expected
+	fn next_line_around<'a>(block: &FormatBlock, items: &[Item<'a>], pos: &mut usize, column_x: f32, y: f32, line_height: f32, indent: f32) -> Line<'a> {
+		let start = *pos;
+		let column_width = block.get_column_width();
+		let mut height = line_height.max(1.0);
+		// a part narrower than the next word is skipped, instead of breaking the word in it,
+		// the whole column is always used, so the text moves down past the exclusions
+		let min_width = Self::get_word_width(items, start) + indent;
+
+		for _ in 0..3 {
+			let band = block
+				.get_free_band(column_x, column_width, y, y + height)
+				.filter(|band| band.1 >= min_width || band.1 >= column_width);
+
+			*pos = start;
+			let (x, width) = match band {
+				Some((x, width)) if width > indent => (x + indent, width - indent),
+				_ => {
+					let mut line = Line::new();
+					line.x = column_x;
+					line.max_width = column_width;
+					line.height = height;
+					return line;
 				}
-				caret.y -= line.descent;
-			}
+			};
+
+			let mut line = Self::next_line(block, items, pos, x - column_x, width);
+			line.x = x;
+			line.max_width = width;
+			if line.height <= height {return line};
+			height = line.height;
 		}
+
+		*pos = start;
+		let mut line = Self::next_line(block, items, pos, indent, column_width - indent);
+		line.x = column_x + indent;
+		line.max_width = column_width - indent;
+		line
 	}
 
-}
 
+	fn column_breaks(lines: &[Line], column_height: f32) -> Vec<bool> {
+		let mut y = 0.0;
+		lines
+			.iter()
+			.map(|line| {
+				let is_break = y > 0.0 && y + line.height - line.descent > column_height;
+				if is_break {y = 0.0};
+				y += line.height;
+				is_break
+			})
+			.collect()
+	}
 
-fn can_draw(rect: Rect<i32>, w:i32, h:i32) -> bool {
-	if
-		rect.max.x < 0 ||
-		rect.max.x > w ||
-		rect.max.y < 0 ||
-		rect.max.y > h
-		{false} else {true}
-}
 
-fn is_font_need_update(a: &Option<String>, b: &Option<String>) -> bool {
-	match (a, b) {
-		(None, None) => false,
-		(Some(_), None) => true,
-		(None, Some(_)) => true,
-		(Some(s_a), Some(s_b)) => s_a != s_b,
+	// the smallest column height that lays out the lines into the block columns
+	fn balance_columns(lines: &[Line], columns: usize) -> f32 {
+		let count = |height: f32| Self::column_breaks(lines, height).iter().filter(|b| **b).count() + 1;
+
+		let total: f32 = lines.iter().map(|line| line.height).sum();
+		let descent = lines.iter().map(|line| -line.descent).fold(0.0, f32::max);
+		let mut lo = total / columns as f32;
+		let mut hi = total + descent;
+
+		for _ in 0..20 {
+			let mid = (lo + hi) / 2.0;
+			if count(mid) > columns {lo = mid} else {hi = mid}
+		}
+		hi
 	}
-}
 
 
-// TODO: if need 
-// http://www.fileformat.info/info/unicode/category/Zs/list.htm
-// https://en.wikipedia.org/wiki/Whitespace_character
-// http://www.unicode.org/Public/UNIDATA/UnicodeData.txt
-// http://unicode.org/reports/tr14/
-// or 
-// https://crates.io/crates/xi-unicode
+	// columns without a fixed height are balanced, otherwise lines keep the position from fill_frame
+	fn position_lines(block: &FormatBlock, render_block: &mut RenderBlock) {
+		let is_balanced = block.columns > 1 && (block.column_balance || block.height == 0.0);
+		if !is_balanced || !block.exclusions.is_empty() {return};
 
-static LINE_BREAK: &[char] = &['↵', '', '', '\n', '', ' ', ' '];
+		let column_width = block.get_column_width();
+		let mut column_height = Self::balance_columns(&render_block.lines, block.columns);
+		if block.height > 0.0 {
+			column_height = column_height.min(block.height);
+		}
+
+		let breaks = Self::column_breaks(&render_block.lines, column_height);
+
+		let mut column = 0;
+		let mut y = 0.0;
+		for (line, is_break) in render_block.lines.iter_mut().zip(breaks) {
+			if is_break && column < block.columns - 1 {
+				column += 1;
+				y = 0.0;
+			}
+			line.x = column as f32 * (column_width + block.column_gap) + line.indent;
+			line.y = y;
+			line.max_width = column_width - line.indent;
+			y += line.height;
+		}
+	}
+
+	fn apply_overflow(block: &FormatBlock, render_block: &mut RenderBlock) {
+		let mut visible = render_block.lines.len();
+
+		if let Some(max_lines) = block.max_lines {
+			visible = visible.min(max_lines);
+		}
+
+		if block.overflow != Overflow::Visible && block.height > 0.0 {
+			let fit_lines = render_block.lines
+				.iter()
+				.take_while(|line| line.y + line.height - line.descent <= block.height)
+				.count();
+			visible = visible.min(fit_lines);
+		}
+
+		if block.overflow == Overflow::Ellipsis {
+			visible = visible.max(1);
+		}
+
+		if visible >= render_block.lines.len() {return};
+
+		render_block.lines.truncate(visible);
+		render_block.truncated = true;
+
+		if block.overflow == Overflow::Ellipsis {
+			let line = render_block.get_last_line();
+			// the ellipsis takes the font and the paint of the text, not of an image at the end
+			let last = line.glyphs.iter().rev().find(|(_, chunk, ..)| chunk.image.is_none());
+			let (glyph, mut chunk) = match last.or_else(|| line.glyphs.last()) {
+				Some((glyph, chunk, ..)) => (glyph.clone(), chunk.clone()),
+				None => return,
+			};
+			chunk.image = None;
+			let font = match glyph.font() {
+				Some(font) => font,
+				None => return,
+			};
+
+			let mut ellipsis = vec![font.glyph('…')];
+			if ellipsis[0].id().0 == 0 {
+				ellipsis = vec![font.glyph('.'), font.glyph('.'), font.glyph('.')];
+			}
+			let ellipsis: Vec<_> = ellipsis
+				.into_iter()
+				.map(|g| g.scaled(glyph.scale()))
+				.collect();
+			let ellipsis_width: f32 = ellipsis
+				.iter()
+				.map(|g| g.h_metrics().advance_width)
+				.sum();
+
+			let mut line_width: f32 = line.glyphs.iter().map(|e| e.3).sum();
+			while let Some((_, _, symbol, symbol_width)) = line.glyphs.last() {
+				let overflow = line.max_width > 0.0 && line_width + ellipsis_width > line.max_width;
+				if !overflow && !is_can_line_break(*symbol) {break};
+				line_width -= *symbol_width;
+				line.glyphs.pop();
+			}
+
+			for g in ellipsis {
+				let width = g.h_metrics().advance_width;
+				line.glyphs.push((g, chunk.clone(), '…', width));
+			}
+		}
+
+		render_block.update_size();
+	}
+
+
+	fn is_block_fit(block: &FormatBlock, render_block: &RenderBlock) -> bool {
+		let width_fit = block.width == 0.0 || render_block.lines
+			.iter()
+			.all(|line| line.width <= line.max_width);
+		let height_fit = block.height == 0.0 || render_block.height <= block.height;
+		// a smaller font is better than a word broken in the middle
+		let words_fit = render_block.lines.iter().all(|line| !line.split_word);
+		width_fit && height_fit && words_fit
+	}
+
+
+	// binary search of the largest font scale that still fits the block
+	fn fit_block<'a>(block: &mut FormatBlock, dpi_factor: f32, fonts: &'a[(String, Font<'a>, FontTables)]) -> RenderBlock<'a> {
+		let (min_size, max_size) = match block.chunk.font_size_range() {
+			Some(range) => range,
+			None => return Self::format_block(block, 1.0, dpi_factor, fonts),
+		};
+
+		let min_scale = block.min_font_size.unwrap_or(1) as f32 / min_size as f32;
+		let max_scale = block.max_font_size.map(|size| size as f32 / max_size as f32);
+
+		let (mut lo, hi) = match block.fit {
+			BlockFit::Shrink => (min_scale.min(1.0), Some(1.0)),
+			BlockFit::Grow => (1.0, max_scale.map(|s| s.max(1.0))),
+			_ => (min_scale, max_scale),
+		};
+
+		let mut hi = match hi {
+			Some(hi) if Self::is_block_fit(block, &Self::format_block(block, hi, dpi_factor, fonts)) => {
+				lo = hi;
+				hi
+			}
+			Some(hi) => hi,
+			None => {
+				let mut hi = lo.max(1.0);
+				for _ in 0..16 {
+					if !Self::is_block_fit(block, &Self::format_block(block, hi, dpi_factor, fonts)) {break};
+					lo = hi;
+					hi *= 2.0;
+				}
+				hi
+			}
+		};
+
+		for _ in 0..16 {
+			if hi - lo < 0.001 {break};
+			let mid = (lo + hi) / 2.0;
+			if Self::is_block_fit(block, &Self::format_block(block, mid, dpi_factor, fonts)) {
+				lo = mid;
+			} else {
+				hi = mid;
+			}
+		}
+
+		block.chunk.scale_font_size(lo);
+		Self::format_block(block, 1.0, dpi_factor, fonts)
+	}
+
+
+	// page_size: (width, height), margins: (top, right, bottom, left)
+	pub fn paginate<'a>(mut format_blocks: Vec<FormatBlock>, dpi_factor: f32, fonts: &'a[(String, Font<'a>, FontTables)], page_size: (f32, f32), margins: (f32, f32, f32, f32)) -> Vec<Layout<'a>> {
+		let (page_width, page_height) = page_size;
+		let (margin_top, margin_right, margin_bottom, margin_left) = margins;
+		let content_width = page_width - margin_left - margin_right;
+		let content_height = page_height - margin_top - margin_bottom;
+
+		for block in format_blocks.iter_mut() {
+			if block.width == 0.0 {block.width = content_width};
+		}
+
+		let new_page = || Layout {
+			blocks: Vec::new(),
+			truncated: false,
+			width: page_width,
+			height: page_height,
+			x: 0.0,
+			y: 0.0,
+		};
+
+		let mut pages = vec![new_page()];
+		let mut y = 0.0;
+
+		for (mut block, mut render_block) in Self::format(format_blocks, dpi_factor, fonts).blocks {
+			if block.page_break_before && y > 0.0 {
+				pages.push(new_page());
+				y = 0.0;
+			}
+
+			let is_splittable = !block.keep_together && block.columns < 2 && block.table.is_none();
+			let block_height = if block.height > 0.0 {block.height} else {render_block.height};
+
+			if block_height > content_height - y - block.margin_top && y > 0.0 && (!is_splittable || block_height <= content_height && block.keep_together) {
+				pages.push(new_page());
+				y = 0.0;
+			}
+
+			let mut is_split = false;
+
+			loop {
+				let margin = if y > 0.0 {block.margin_top} else {0.0};
+				let remaining = content_height - y - margin;
+				let lines_count = render_block.lines.len();
+
+				let fit_count = render_block.lines
+					.iter()
+					.take_while(|line| line.y + line.height - line.descent <= remaining)
+					.count();
+				let mut count = fit_count;
+
+				if !is_splittable || count >= lines_count || render_block.height <= remaining {
+					count = lines_count;
+				} else {
+					if lines_count - count < block.widows {
+						count = lines_count.saturating_sub(block.widows);
+					}
+					if count < block.orphans {
+						count = 0;
+					}
+					// on an empty page the lines that fit are better than an endless move
+					if count == 0 && y == 0.0 {
+						count = fit_count.max(1);
+					}
+				}
+
+				if count == 0 && lines_count > 0 {
+					pages.push(new_page());
+					y = 0.0;
+					continue;
+				}
+
+				let is_last = count == lines_count;
+				let mut fragment = if is_last {
+					std::mem::replace(&mut render_block, block.to_render_block())
+				} else {
+					is_split = true;
+					render_block.split_lines(count)
+				};
+				let mut fragment_block = if is_last {
+					std::mem::replace(&mut block, FormatBlock::new())
+				} else {
+					block.new_empty()
+				};
+
+				if is_split {
+					fragment_block.height = 0.0;
+				}
+				fragment_block.x += margin_left;
+				fragment_block.y = margin_top + y + margin;
+				fragment.x = fragment_block.x;
+				fragment.y = fragment_block.y;
+
+				let height = if fragment_block.height > 0.0 {fragment_block.height} else {fragment.height};
+				y += margin + height + fragment_block.margin_bottom;
+
+				let page = pages.last_mut().unwrap();
+				page.truncated |= fragment.truncated;
+				page.blocks.push((fragment_block, fragment));
+
+				if is_last {break};
+				pages.push(new_page());
+				y = 0.0;
+			}
+		}
+
+		pages
+	}
+
+
+	pub fn measure<'a>(format_blocks: Vec<FormatBlock>, dpi_factor: f32, fonts: &'a[(String, Font<'a>, FontTables)]) -> Metrics {
+		let mut layout = Self::format(format_blocks, dpi_factor, fonts);
+		layout.calk_view();
+
+		Metrics {
+			width: layout.width,
+			height: layout.height,
+			x: layout.x,
+			y: layout.y,
+			blocks: layout.blocks
+				.iter()
+				.map(|(f_block, r_block)| BlockMetrics::new(f_block, r_block))
+				.collect(),
+		}
+	}
+
+
+	// single line of text in the given font, without wrapping
+	pub fn measure_str<'a>(font: &'a (String, Font<'a>, FontTables), font_size: usize, text: &str) -> LineMetrics {
+		let mut block = FormatBlock::new();
+		block.chunk.font_size = font_size;
+		block.chunk.chunks.push(FormatChunks::String(text.to_string()));
+
+		let metrics = Self::measure(vec![block], 1.0, std::slice::from_ref(font));
+		metrics.blocks[0].lines[0]
+	}
+
+
+	pub fn render<T: ImgBufferTrait>( layout: &Layout, buffer: &mut T )
+	{
+		for ( f_block, r_block ) in layout.blocks.iter() {
+			let origin = point(f_block.x - layout.x, f_block.y - layout.y);
+			Self::render_block(f_block, r_block, origin, buffer);
+		}
+	}
+
+
+	fn render_block<T: ImgBufferTrait>( f_block: &FormatBlock, r_block: &RenderBlock, origin: Point<f32>, buffer: &mut T )
+	{
+		let mut caret = point(0.0, 0.0);
+		let buffer_width = buffer.width() as i32;
+		let buffer_height = buffer.height() as i32;
+
+		if f_block.border_width > 0.0 {
+			let outset = f_block.padding + f_block.border_width;
+			let width = if f_block.width > 0.0 {f_block.width} else {r_block.width};
+			let height = if f_block.height > 0.0 {f_block.height} else {r_block.height};
+			draw_border(
+				buffer,
+				Rect {
+					min: point(origin.x - outset, origin.y - outset),
+					max: point(origin.x + width + outset, origin.y + height + outset),
+				},
+				f_block.border_width,
+				&f_block.border_color,
+			);
+		}
+
+		if let Some(table) = &f_block.table {
+			for (cell, render_cell) in table.cells().zip(r_block.cells.iter()) {
+				Self::render_block(cell, render_cell, point(origin.x + cell.x, origin.y + cell.y), buffer);
+			}
+		}
+
+		let offset = point(origin.x, origin.y + f_block.get_vertical_offset(r_block));
+
+		let lines_count = r_block.lines.len();
+		let baselines = r_block.baselines();
+
+		let clip = if f_block.overflow == Overflow::Visible {None} else {
+			let size = |v: f32| if v > 0.0 {v} else {buffer_width.max(buffer_height) as f32};
+			Some(Rect {
+				min: point(origin.x.floor() as i32, origin.y.floor() as i32),
+				max: point((origin.x + size(f_block.width)).ceil() as i32, (origin.y + size(f_block.height)).ceil() as i32),
+			})
+		};
+
+		// positions of the glyphs of all lines, so backgrounds don't cover the glyphs of the line above
+		let mut line_positions = Vec::with_capacity(lines_count);
+
+		for (i, line) in r_block.lines.iter().enumerate() {
+			caret.y = offset.y + baselines[i];
+
+			let mut space_inc = 0.0; 
+			
+			match line.text_align.unwrap_or(f_block.text_align) {
+				TextAlignHorizontal::Right => {caret.x = offset.x + line.x + line.max_width - line.width;}
+				TextAlignHorizontal::Center => {caret.x = offset.x + line.x + ((line.max_width - line.width)/2.0);}
+				TextAlignHorizontal::Justify => {
+					caret.x = offset.x + line.x;
+					if !line.force_break {
+						let c = line.glyphs
+							.iter()
+							.filter( |(_,_,symbol,_)| *symbol == ' ')
+							.count();
+						space_inc = (line.max_width - line.width) / (c as f32);
+					}
+				} 
+				_ => {caret.x = offset.x + line.x;}
+			}
+
+			let positions: Vec<f32> = line.glyphs
+				.iter()
+				.map(|(_, _, symbol, symbol_width)| {
+					if *symbol == ' ' { caret.x += space_inc };
+					let x = caret.x;
+					caret.x += symbol_width;
+					x
+				})
+				.collect();
+
+			draw_backgrounds(buffer, &line.glyphs, &positions, caret.y, clip);
+			line_positions.push((caret.y, positions));
+		}
+
+		// shadows with the same offset, blur and color are drawn at once, the first shadow is on top
+		let mut shadows: Vec<(TextShadow, BlendMode)> = Vec::new();
+		for line in r_block.lines.iter() {
+			for (_, chunk, ..) in line.glyphs.iter() {
+				for shadow in chunk.text_shadows.iter() {
+					let shadow = (TextShadow { color: Some(shadow.color.unwrap_or(chunk.color)), ..*shadow }, chunk.blend_mode);
+					if !shadows.contains(&shadow) {
+						shadows.push(shadow);
+					}
+				}
+			}
+		}
+		for (shadow, blend_mode) in shadows.iter().rev() {
+			draw_shadow(buffer, &r_block.lines, &line_positions, shadow, *blend_mode, clip);
+		}
+
+		// strokes of all lines are under the fill, so they don't cover the neighbouring glyphs
+		for (line, (baseline, positions)) in r_block.lines.iter().zip(line_positions.iter()) {
+			for ((scaled_glyph, chunk, ..), x) in line.glyphs.iter().zip(positions.iter()) {
+				if chunk.stroke.width > 0.0 {
+					draw_stroke(buffer, scaled_glyph, point(*x, *baseline), chunk, clip);
+				}
+			}
+		}
+
+		let block_area = Rect {
+			min: origin,
+			max: point(
+				origin.x + if f_block.width > 0.0 {f_block.width} else {r_block.width},
+				origin.y + if f_block.height > 0.0 {f_block.height} else {r_block.height},
+			),
+		};
+
+		for (line, (baseline, positions)) in r_block.lines.iter().zip(line_positions.iter()) {
+			let marker_width: f32 = line.marker.iter().map(|e| e.3).sum();
+			let mut marker_caret = offset.x + line.x - marker_width;
+			let marker_positions: Vec<f32> = line.marker
+				.iter()
+				.map(|e| {
+					marker_caret += e.3;
+					marker_caret - e.3
+				})
+				.collect();
+			let marker_areas = get_paint_areas(&line.marker, &marker_positions, *baseline, block_area);
+			for (((scaled_glyph, chunk, ..), x), area) in line.marker.iter().zip(marker_positions.iter()).zip(marker_areas) {
+				draw_glyph(buffer, scaled_glyph, point(*x, *baseline), chunk, area, clip);
+			}
+
+			// underlines and overlines are under the glyphs, line-through over them
+			draw_decorations(buffer, &line.glyphs, positions, *baseline, false, clip);
+			let areas = get_paint_areas(&line.glyphs, positions, *baseline, block_area);
+			for (((scaled_glyph, chunk, ..), x), area) in line.glyphs.iter().zip(positions.iter()).zip(areas) {
+				match &chunk.image {
+					Some(image) => draw_image(buffer, image, point(*x, *baseline - chunk.ascent), chunk, clip),
+					None => draw_glyph(buffer, scaled_glyph, point(*x, *baseline), chunk, area, clip),
+				}
+			}
+			draw_decorations(buffer, &line.glyphs, positions, *baseline, true, clip);
+		}
+	}
+
+}
+
+
+// area is the box the paint of the chunk is stretched over
+fn draw_glyph<T: ImgBufferTrait>(buffer: &mut T, scaled_glyph: &ScaledGlyph, caret: Point<f32>, chunk: &RenderChunk, area: Rect<f32>, clip: Option<Rect<i32>>) {
+	let buffer_width = buffer.width() as i32;
+	let buffer_height = buffer.height() as i32;
+	let solid = match chunk.paint {
+		Paint::Solid(color) => Some(color),
+		_ => None,
+	};
+	let positioned_glyph = scaled_glyph.clone().positioned(point(caret.x, caret.y - chunk.baseline_shift));
+
+	if let Some(bounding_box) = positioned_glyph.pixel_bounding_box() {
+		if can_draw(bounding_box, buffer_width, buffer_height) {
+			positioned_glyph.draw(|x, y, v| {
+				let x = bounding_box.min.x + (x as i32);
+				let y = bounding_box.min.y + (y as i32);
+
+				if x < 0 {return};
+				if y < 0 {return};
+				if let Some(clip) = clip {
+					if x < clip.min.x || y < clip.min.y || x >= clip.max.x || y >= clip.max.y {return};
+				}
+
+				let color = solid.unwrap_or_else(|| chunk.paint.get_color(point(x as f32 + 0.5, y as f32 + 0.5), area));
+				buffer.blend_pixel(x as usize, y as usize, &color, v * chunk.opacity, chunk.blend_mode);
+			});
+		}
+	}
+}
+
+
+// the image is scaled with bilinear filtering, from the top left corner
+fn draw_image<T: ImgBufferTrait>(buffer: &mut T, image: &InlineImage, origin: Point<f32>, chunk: &RenderChunk, clip: Option<Rect<i32>>) {
+	let source = &image.image;
+	if source.width == 0 || source.height == 0 || image.width <= 0.0 || image.height <= 0.0 {return};
+	let buffer_width = buffer.width() as i32;
+	let buffer_height = buffer.height() as i32;
+
+	let min = point(origin.x.round() as i32, origin.y.round() as i32);
+	let max = point((origin.x + image.width).round() as i32, (origin.y + image.height).round() as i32);
+	let (scale_x, scale_y) = (source.width as f32 / image.width, source.height as f32 / image.height);
+
+	for y in min.y.max(0)..max.y.min(buffer_height) {
+		for x in min.x.max(0)..max.x.min(buffer_width) {
+			if let Some(clip) = clip {
+				if x < clip.min.x || y < clip.min.y || x >= clip.max.x || y >= clip.max.y {continue};
+			}
+			let sx = ((x - min.x) as f32 + 0.5) * scale_x - 0.5;
+			let sy = ((y - min.y) as f32 + 0.5) * scale_y - 0.5;
+			let color = get_image_pixel(source, sx, sy);
+			buffer.blend_pixel(x as usize, y as usize, &color, chunk.opacity, chunk.blend_mode);
+		}
+	}
+}
+
+
+fn get_image_pixel(image: &ImgBuffer, x: f32, y: f32) -> ColorRGBA {
+	let x = x.clamp(0.0, (image.width - 1) as f32);
+	let y = y.clamp(0.0, (image.height - 1) as f32);
+	let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+	let (x1, y1) = ((x0 + 1).min(image.width - 1), (y0 + 1).min(image.height - 1));
+	let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+
+	let pixel = |x: usize, y: usize, c: usize| image.buffer[(y * image.width + x) * 4 + c] as f32;
+	let mut color = [0; 4];
+	for (c, e) in color.iter_mut().enumerate() {
+		let top = pixel(x0, y0, c) * (1.0 - fx) + pixel(x1, y0, c) * fx;
+		let bottom = pixel(x0, y1, c) * (1.0 - fx) + pixel(x1, y1, c) * fx;
+		*e = (top * (1.0 - fy) + bottom * fy).round() as u8;
+	}
+	color
+}
+
+
+// boxes of the glyphs, of the runs with the same paint or of the block
+fn get_paint_areas(glyphs: &[(ScaledGlyph, RenderChunk, char, f32)], positions: &[f32], baseline: f32, block_area: Rect<f32>) -> Vec<Rect<f32>> {
+	let mut areas: Vec<Rect<f32>> = glyphs
+		.iter()
+		.zip(positions.iter())
+		.map(|((_, chunk, _, width), x)| Rect {
+			min: point(*x, baseline - chunk.ascent),
+			max: point(x + width, baseline - chunk.descent),
+		})
+		.collect();
+
+	for (start, last) in get_runs(glyphs, |a, b| a.paint == b.paint && a.paint_area == b.paint_area) {
+		let area = match glyphs[start].1.paint_area {
+			PaintArea::Glyph => continue,
+			PaintArea::Block => block_area,
+			PaintArea::Span => areas[start..last]
+				.iter()
+				.fold(areas[start], |a, e| Rect {
+					min: point(a.min.x.min(e.min.x), a.min.y.min(e.min.y)),
+					max: point(a.max.x.max(e.max.x), a.max.y.max(e.max.y)),
+				}),
+		};
+		for e in areas[start..last].iter_mut() {
+			*e = area;
+		}
+	}
+	areas
+}
+
+
+// glyphs with the shadow are drawn into the mask, that is blurred and drawn with the color of the shadow
+fn draw_shadow<T: ImgBufferTrait>(buffer: &mut T, lines: &[Line], line_positions: &[(f32, Vec<f32>)], shadow: &TextShadow, blend_mode: BlendMode, clip: Option<Rect<i32>>) {
+	let mut glyphs = Vec::new();
+	for (line, (baseline, positions)) in lines.iter().zip(line_positions.iter()) {
+		for ((scaled_glyph, chunk, ..), x) in line.glyphs.iter().zip(positions.iter()) {
+			let has_shadow = chunk.text_shadows
+				.iter()
+				.any(|e| e.x == shadow.x && e.y == shadow.y && e.blur == shadow.blur && e.color.unwrap_or(chunk.color) == shadow.color.unwrap());
+			if has_shadow && chunk.blend_mode == blend_mode {
+				let caret = point(x + shadow.x, baseline - chunk.baseline_shift + shadow.y);
+				glyphs.push((scaled_glyph.clone().positioned(caret), chunk.opacity));
+			}
+		}
+	}
+
+	let margin = (shadow.blur * 1.5).ceil() as i32 + 1;
+	let bounds = glyphs
+		.iter()
+		.filter_map(|(e, _)| e.pixel_bounding_box())
+		.fold(None, |bounds: Option<Rect<i32>>, e| Some(match bounds {
+			None => e,
+			Some(b) => Rect {
+				min: point(b.min.x.min(e.min.x), b.min.y.min(e.min.y)),
+				max: point(b.max.x.max(e.max.x), b.max.y.max(e.max.y)),
+			},
+		}));
+	let bounds = match bounds {
+		Some(b) => Rect {
+			min: point((b.min.x - margin).max(-margin), (b.min.y - margin).max(-margin)),
+			max: point((b.max.x + margin).min(buffer.width() as i32 + margin), (b.max.y + margin).min(buffer.height() as i32 + margin)),
+		},
+		None => return,
+	};
+
+	let mut mask = Mask::new(bounds);
+	for (positioned_glyph, opacity) in glyphs.iter() {
+		if let Some(bounding_box) = positioned_glyph.pixel_bounding_box() {
+			positioned_glyph.draw(|x, y, v| {
+				mask.add(bounding_box.min.x + x as i32, bounding_box.min.y + y as i32, v * opacity);
+			});
+		}
+	}
+	mask.blur(shadow.blur / 2.0);
+	mask.draw(buffer, &shadow.color.unwrap(), blend_mode, clip);
+}
+
+
+// coverage of each pixel from the distance to the stroke: a box along every segment of the
+// flattened outline, and a disk, a miter or a bevel at every join
+fn draw_stroke<T: ImgBufferTrait>(buffer: &mut T, scaled_glyph: &ScaledGlyph, caret: Point<f32>, chunk: &RenderChunk, clip: Option<Rect<i32>>) {
+	let buffer_width = buffer.width() as i32;
+	let buffer_height = buffer.height() as i32;
+	let stroke = chunk.stroke;
+	let half = stroke.width / 2.0;
+	let origin = point(caret.x, caret.y - chunk.baseline_shift);
+	let positioned_glyph = scaled_glyph.clone().positioned(origin);
+
+	let (bounding_box, contours) = match (positioned_glyph.pixel_bounding_box(), scaled_glyph.shape()) {
+		(Some(bounding_box), Some(contours)) => (bounding_box, contours),
+		_ => return,
+	};
+	// like the fill, glyphs out of the buffer are skipped
+	if !can_draw(bounding_box, buffer_width, buffer_height) {return};
+
+	let mut segments = Vec::new();
+	let mut vertices = Vec::new();
+	let mut joins: Vec<Vec<Point<f32>>> = Vec::new();
+
+	for contour in contours.iter() {
+		let polyline = flatten_contour(contour, origin);
+		let count = polyline.len();
+		if count < 2 {continue};
+
+		for i in 0..count {
+			let (prev, current, next) = (polyline[(i + count - 1) % count], polyline[i], polyline[(i + 1) % count]);
+			let (d0, d1) = (normalize(current - prev), normalize(next - current));
+			// the boxes overlap the joins a bit, the edges they share would be half transparent
+			segments.push((current - d1 * 0.5, next + d1 * 0.5));
+
+			if stroke.line_join == LineJoin::Round {
+				vertices.push(current);
+				continue;
+			}
+			if d0 == d1 {continue};
+
+			// normals on the outer side of the turn
+			let side = (d0.x * -d1.y + d0.y * d1.x).signum();
+			if side == 0.0 {continue};
+			let n0 = vector(-d0.y * side, d0.x * side);
+			let n1 = vector(-d1.y * side, d1.x * side);
+			let (a, b) = (current + n0 * half, current + n1 * half);
+
+			let bisector = normalize(n0 + n1);
+			let cos = bisector.x * n0.x + bisector.y * n0.y;
+			if cos > 0.0 && 1.0 / cos <= stroke.miter_limit {
+				joins.push(vec![current, a, current + bisector * (half / cos), b]);
+			} else {
+				joins.push(vec![current, a, b]);
+			}
+		}
+	}
+
+	let margin = (half * stroke.miter_limit).ceil() as i32 + 1;
+	let mut min = point((bounding_box.min.x - margin).max(0), (bounding_box.min.y - margin).max(0));
+	let mut max = point((bounding_box.max.x + margin).min(buffer_width), (bounding_box.max.y + margin).min(buffer_height));
+	if let Some(clip) = clip {
+		min = point(min.x.max(clip.min.x), min.y.max(clip.min.y));
+		max = point(max.x.min(clip.max.x), max.y.min(clip.max.y));
+	}
+
+	// only the parts of the outline near the row can cover its pixels
+	let reach = half + 1.0;
+	let is_near = |y: f32, top: f32, bottom: f32| y >= top - reach && y <= bottom + reach;
+
+	for y in min.y..max.y {
+		let py = y as f32 + 0.5;
+		let row_segments: Vec<_> = segments.iter().filter(|(p0, p1)| is_near(py, p0.y.min(p1.y), p0.y.max(p1.y))).collect();
+		let row_vertices: Vec<_> = vertices.iter().filter(|v| is_near(py, v.y, v.y)).collect();
+		let row_joins: Vec<_> = joins
+			.iter()
+			.filter(|join| {
+				let top = join.iter().map(|p| p.y).fold(f32::MAX, f32::min);
+				let bottom = join.iter().map(|p| p.y).fold(f32::MIN, f32::max);
+				is_near(py, top, bottom)
+			})
+			.collect();
+
+		for x in min.x..max.x {
+			let p = point(x as f32 + 0.5, py);
+
+			let mut distance = f32::MAX;
+			for (p0, p1) in row_segments.iter() {
+				distance = distance.min(get_segment_distance(p, *p0, *p1, half));
+			}
+			for vertex in row_vertices.iter() {
+				distance = distance.min(length(p - **vertex) - half);
+			}
+			for join in row_joins.iter() {
+				distance = distance.min(get_polygon_distance(p, join));
+			}
+
+			let v = (0.5 - distance).clamp(0.0, 1.0);
+			if v > 0.0 {
+				buffer.blend_pixel(x as usize, y as usize, &stroke.color, v * chunk.opacity, chunk.blend_mode);
+			}
+		}
+	}
+}
+
+
+// closed polyline of the contour, the curves are split by their length,
+// the shape of the glyph has the y axis up from the origin
+fn flatten_contour(contour: &Contour, origin: Point<f32>) -> Vec<Point<f32>> {
+	let mut polyline = Vec::new();
+	for segment in contour.segments.iter() {
+		match segment {
+			Segment::Line(line) => {
+				polyline.push(line.p[0]);
+			}
+			Segment::Curve(curve) => {
+				let [p0, p1, p2] = curve.p;
+				let steps = ((length(p1 - p0) + length(p2 - p1)) / 2.0).ceil().clamp(1.0, 16.0) as usize;
+				for i in 0..steps {
+					let t = i as f32 / steps as f32;
+					let (a, b, c) = ((1.0 - t) * (1.0 - t), 2.0 * t * (1.0 - t), t * t);
+					polyline.push(point(p0.x * a + p1.x * b + p2.x * c, p0.y * a + p1.y * b + p2.y * c));
+				}
+			}
+		}
+	}
+	polyline.dedup_by(|a, b| length(*a - *b) < 0.01);
+	polyline
+		.iter()
+		.map(|p| point(origin.x + p.x, origin.y - p.y))
+		.collect()
+}
+
+
+// signed distance to the box around the segment, 2*half wide
+fn get_segment_distance(p: Point<f32>, p0: Point<f32>, p1: Point<f32>, half: f32) -> f32 {
+	let len = length(p1 - p0);
+	if len == 0.0 {return length(p - p0) - half};
+	let direction = (p1 - p0) * (1.0 / len);
+	let v = p - p0;
+	let along = v.x * direction.x + v.y * direction.y;
+	let across = v.x * -direction.y + v.y * direction.x;
+	let qx = (along - len / 2.0).abs() - len / 2.0;
+	let qy = across.abs() - half;
+	(qx.max(0.0).powi(2) + qy.max(0.0).powi(2)).sqrt() + qx.max(qy).min(0.0)
+}
+
+
+// distance to the edges of the convex polygon, negative inside
+fn get_polygon_distance(p: Point<f32>, polygon: &[Point<f32>]) -> f32 {
+	let mut distance = f32::MAX;
+	let mut sign = 0.0;
+	let mut is_inside = true;
+	for i in 0..polygon.len() {
+		let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+		distance = distance.min(get_segment_distance(p, a, b, 0.0));
+		let cross = (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x);
+		if cross != 0.0 {
+			if sign == 0.0 {sign = cross.signum()};
+			if cross.signum() != sign {is_inside = false};
+		}
+	}
+	if is_inside {-distance} else {distance}
+}
+
+
+fn length(v: Vector<f32>) -> f32 {
+	(v.x * v.x + v.y * v.y).sqrt()
+}
+
+
+fn normalize(v: Vector<f32>) -> Vector<f32> {
+	let len = length(v);
+	if len == 0.0 {v} else {v * (1.0 / len)}
+}
+
+
+// lines of each run of glyphs with the same decoration
+fn draw_decorations<T: ImgBufferTrait>(buffer: &mut T, glyphs: &[(ScaledGlyph, RenderChunk, char, f32)], positions: &[f32], baseline: f32, is_line_through: bool, clip: Option<Rect<i32>>) {
+	let runs = get_runs(glyphs, |a, b| {
+		a.decoration == b.decoration && a.decoration_metrics == b.decoration_metrics && a.color == b.color && a.baseline_shift == b.baseline_shift
+			&& a.opacity == b.opacity && a.blend_mode == b.blend_mode
+	});
+
+	for (start, last) in runs {
+		let chunk = &glyphs[start].1;
+		let baseline = baseline - chunk.baseline_shift;
+		let decoration = chunk.decoration;
+		if decoration.is_some() {
+			let metrics = chunk.decoration_metrics;
+			let color = with_opacity(decoration.color.unwrap_or(chunk.color), chunk.opacity);
+			let from = positions[start];
+			let to = positions[last-1] + glyphs[last-1].3;
+
+			let mut lines = Vec::new();
+			if is_line_through && decoration.line_through {
+				lines.push((metrics.line_through_offset, decoration.thickness.unwrap_or(metrics.line_through_thickness), false));
+			}
+			if !is_line_through && decoration.underline {
+				lines.push((metrics.underline_offset, decoration.thickness.unwrap_or(metrics.underline_thickness), decoration.skip_ink));
+			}
+			if !is_line_through && decoration.overline {
+				lines.push((metrics.overline_offset, decoration.thickness.unwrap_or(metrics.underline_thickness), false));
+			}
+
+			for (offset, thickness, skip_ink) in lines {
+				let thickness = thickness.max(1.0);
+				let top = baseline + offset;
+				let skip = if skip_ink {
+					get_ink_skip(&glyphs[start..last], &positions[start..last], baseline, from, top, thickness)
+				} else {
+					Vec::new()
+				};
+				draw_decoration_line(buffer, (from, to), (top, thickness), decoration.style, (&color, chunk.blend_mode), &skip, clip);
+			}
+		}
+	}
+}
+
+
+// boxes from the highest ascent to the lowest descent of the run
+fn draw_backgrounds<T: ImgBufferTrait>(buffer: &mut T, glyphs: &[(ScaledGlyph, RenderChunk, char, f32)], positions: &[f32], baseline: f32, clip: Option<Rect<i32>>) {
+	let runs = get_runs(glyphs, |a, b| a.background == b.background && a.opacity == b.opacity && a.blend_mode == b.blend_mode);
+
+	for (start, last) in runs {
+		let chunk = &glyphs[start].1;
+		let background = chunk.background;
+		if let Some(color) = background.color {
+			let color = with_opacity(color, chunk.opacity);
+			let ascent = glyphs[start..last].iter().map(|e| e.1.ascent).fold(0.0, f32::max);
+			let descent = glyphs[start..last].iter().map(|e| e.1.descent).fold(0.0, f32::min);
+			let rect = Rect {
+				min: point(positions[start] - background.padding, baseline - ascent - background.padding),
+				max: point(positions[last-1] + glyphs[last-1].3 + background.padding, baseline - descent + background.padding),
+			};
+			draw_rounded_rect(buffer, rect, background.radius, &color, chunk.blend_mode, clip);
+		}
+	}
+}
+
+
+// ranges start..last of glyphs with the same style, without the trailing spaces
+fn get_runs<F>(glyphs: &[(ScaledGlyph, RenderChunk, char, f32)], is_same: F) -> Vec<(usize, usize)>
+	where F: Fn(&RenderChunk, &RenderChunk) -> bool
+{
+	let mut runs = Vec::new();
+	let mut start = 0;
+
+	while start < glyphs.len() {
+		let mut end = start + 1;
+		while end < glyphs.len() && is_same(&glyphs[start].1, &glyphs[end].1) {
+			end += 1;
+		}
+
+		let mut last = end;
+		while last > start && is_can_line_break(glyphs[last-1].2) {
+			last -= 1;
+		}
+		if last > start {
+			runs.push((start, last));
+		}
+
+		start = end;
+	}
+	runs
+}
+
+
+// columns from `from.floor()` where the glyphs cross the line, with a gap on the sides
+fn get_ink_skip(glyphs: &[(ScaledGlyph, RenderChunk, char, f32)], positions: &[f32], baseline: f32, from: f32, top: f32, thickness: f32) -> Vec<bool> {
+	let gap = thickness.ceil() as i32;
+	let first = from.floor() as i32;
+	let count = (positions[positions.len()-1] + glyphs[glyphs.len()-1].3).ceil() as i32 - first + 1;
+	let mut ink = vec![false; count.max(0) as usize];
+
+	for ((scaled_glyph, ..), x) in glyphs.iter().zip(positions.iter()) {
+		let positioned_glyph = scaled_glyph.clone().positioned(point(*x, baseline));
+		if let Some(bounding_box) = positioned_glyph.pixel_bounding_box() {
+			positioned_glyph.draw(|x, y, v| {
+				let y = (bounding_box.min.y + y as i32) as f32;
+				if v < 0.5 || y + 1.0 <= top || y >= top + thickness {return};
+
+				let x = bounding_box.min.x + x as i32 - first;
+				for i in (x - gap).max(0)..(x + gap + 1).min(ink.len() as i32) {
+					ink[i as usize] = true;
+				}
+			});
+		}
+	}
+
+	ink
+}
+
+
+fn draw_decoration_line<T: ImgBufferTrait>(buffer: &mut T, (from, to): (f32, f32), (top, thickness): (f32, f32), style: DecorationStyle, (color, blend_mode): (&ColorRGBA, BlendMode), skip: &[bool], clip: Option<Rect<i32>>) {
+	let first = from.floor() as i32;
+
+	for x in first..to.ceil() as i32 {
+		if skip.get((x - first) as usize) == Some(&true) {continue};
+
+		let phase = x as f32 - from;
+		match style {
+			DecorationStyle::Solid => {
+				draw_span(buffer, x, top, top + thickness, color, blend_mode, clip);
+			}
+			DecorationStyle::Double => {
+				draw_span(buffer, x, top, top + thickness, color, blend_mode, clip);
+				draw_span(buffer, x, top + thickness * 2.0, top + thickness * 3.0, color, blend_mode, clip);
+			}
+			DecorationStyle::Dotted => {
+				if (phase / thickness).floor() as i32 % 2 == 0 {
+					draw_span(buffer, x, top, top + thickness, color, blend_mode, clip);
+				}
+			}
+			DecorationStyle::Wavy => {
+				let amplitude = thickness * 1.5;
+				let center = top + thickness / 2.0 + amplitude * (phase * std::f32::consts::PI / (amplitude * 2.0)).sin();
+				draw_span(buffer, x, center - thickness / 2.0, center + thickness / 2.0, color, blend_mode, clip);
+			}
+		}
+	}
+}
+
+
+// coverage of each pixel from the distance to the edge of the rounded rect
+fn draw_rounded_rect<T: ImgBufferTrait>(buffer: &mut T, rect: Rect<f32>, radius: f32, color: &ColorRGBA, blend_mode: BlendMode, clip: Option<Rect<i32>>) {
+	let half = ((rect.max.x - rect.min.x) / 2.0, (rect.max.y - rect.min.y) / 2.0);
+	let center = (rect.min.x + half.0, rect.min.y + half.1);
+	let radius = radius.min(half.0).min(half.1).max(0.0);
+
+	for y in (rect.min.y.floor() as i32).max(0)..rect.max.y.ceil() as i32 {
+		for x in (rect.min.x.floor() as i32).max(0)..rect.max.x.ceil() as i32 {
+			if let Some(clip) = clip {
+				if x < clip.min.x || y < clip.min.y || x >= clip.max.x || y >= clip.max.y {continue};
+			}
+			let qx = (x as f32 + 0.5 - center.0).abs() - half.0 + radius;
+			let qy = (y as f32 + 0.5 - center.1).abs() - half.1 + radius;
+			let distance = (qx.max(0.0).powi(2) + qy.max(0.0).powi(2)).sqrt() + qx.max(qy).min(0.0) - radius;
+			let v = (0.5 - distance).clamp(0.0, 1.0);
+			if v > 0.0 {
+				buffer.blend_pixel(x as usize, y as usize, color, v, blend_mode);
+			}
+		}
+	}
+}
+
+
+// vertical part y0..y1 of the pixel column x, with antialiased ends
+fn draw_span<T: ImgBufferTrait>(buffer: &mut T, x: i32, y0: f32, y1: f32, color: &ColorRGBA, blend_mode: BlendMode, clip: Option<Rect<i32>>) {
+	if x < 0 {return};
+
+	for y in (y0.floor() as i32).max(0)..y1.ceil() as i32 {
+		if let Some(clip) = clip {
+			if x < clip.min.x || y < clip.min.y || x >= clip.max.x || y >= clip.max.y {continue};
+		}
+		let v = (y1.min(y as f32 + 1.0) - y0.max(y as f32)).clamp(0.0, 1.0);
+		buffer.blend_pixel(x as usize, y as usize, color, v, blend_mode);
+	}
+}
+
+
+// border_width is inside of the rect
+fn draw_border<T: ImgBufferTrait>(buffer: &mut T, rect: Rect<f32>, border_width: f32, color: &ColorRGBA) {
+	let min_x = (rect.min.x.round() as i32).max(0);
+	let min_y = (rect.min.y.round() as i32).max(0);
+	let max_x = (rect.max.x.round() as i32).min(buffer.width() as i32);
+	let max_y = (rect.max.y.round() as i32).min(buffer.height() as i32);
+	let width = (border_width.round() as i32).max(1);
+
+	for y in min_y..max_y {
+		let is_edge = y < rect.min.y.round() as i32 + width || y >= rect.max.y.round() as i32 - width;
+		for x in min_x..max_x {
+			if is_edge || x < rect.min.x.round() as i32 + width || x >= rect.max.x.round() as i32 - width {
+				buffer.blend_pixel(x as usize, y as usize, color, 1.0, BlendMode::Normal);
+			}
+		}
+	}
+}
+
+
+fn can_draw(rect: Rect<i32>, w:i32, h:i32) -> bool {
+	if
+		rect.max.x < 0 ||
+		rect.max.x > w ||
+		rect.max.y < 0 ||
+		rect.max.y > h
+		{false} else {true}
+}
+
+fn with_opacity(color: ColorRGBA, opacity: f32) -> ColorRGBA {
+	[color[0], color[1], color[2], (color[3] as f32 * opacity).round() as u8]
+}
+
+
+// values of the font tables or common ones, if they are missing
+fn get_decoration_metrics(tables: &FontTables, units: f32, font_size: f32, ascent: f32) -> DecorationMetrics {
+	let value = |v: i16, default: f32| if v != 0 {v as f32 * units} else {default * font_size};
+
+	DecorationMetrics {
+		underline_offset: -value(tables.underline_position, -0.1),
+		underline_thickness: value(tables.underline_thickness, 0.05),
+		overline_offset: -ascent,
+		line_through_offset: -value(tables.strikeout_position, 0.3),
+		line_through_thickness: value(tables.strikeout_thickness, 0.05),
+	}
+}
+
+
+fn is_whitespace_item(item: &Item) -> bool {
+	match item {
+		Item::LineBreak => true,
+		Item::Glyph { symbol, .. } => symbol.is_whitespace(),
+		_ => false,
+	}
+}
+
+
+fn is_font_need_update(a: &Option<String>, b: &Option<String>) -> bool {
+	match (a, b) {
+		(None, None) => false,
+		(Some(_), None) => true,
+		(None, Some(_)) => true,
+		(Some(s_a), Some(s_b)) => s_a != s_b,
+	}
+}
+
+
+// TODO: if need 
+// http://www.fileformat.info/info/unicode/category/Zs/list.htm
+// https://en.wikipedia.org/wiki/Whitespace_character
+// http://www.unicode.org/Public/UNIDATA/UnicodeData.txt
+// http://unicode.org/reports/tr14/
+// or 
+// https://crates.io/crates/xi-unicode
+
+static LINE_BREAK: &[char] = &['↵', '', '', '\n', '', ' ', ' '];
+
+const SOFT_HYPHEN: char = '\u{ad}';
+
+const NO_BREAK_SPACE: char = '\u{a0}';
 
 const CAN_LINE_BREAK: &[char] = &[
 	' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', '　'
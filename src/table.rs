@@ -0,0 +1,38 @@
+use chunk::FormatBlock;
+
+
+// every cell is a block, `format` positions it relative to the table
+#[derive(Debug, Default)]
+pub struct Table {
+	pub rows: Vec<Vec<FormatBlock>>,
+}
+
+
+impl Table {
+
+	pub fn new() -> Self {
+		Self {
+			rows: Vec::new(),
+		}
+	}
+
+	pub fn get_columns_count(&self) -> usize {
+		self.rows
+			.iter()
+			.map(|row| row.len())
+			.max()
+			.unwrap_or(0)
+	}
+
+	pub fn get_last_cell(&mut self) -> Option<&mut FormatBlock> {
+		self.rows
+			.last_mut()
+			.and_then(|row| row.last_mut())
+	}
+
+	pub fn cells(&self) -> impl Iterator<Item = &FormatBlock> {
+		self.rows
+			.iter()
+			.flat_map(|row| row.iter())
+	}
+}
@@ -2,10 +2,17 @@ extern crate xml;
 
 use self::xml::reader::{EventReader, XmlEvent};
 use std::io::BufReader;
+use std::collections::HashMap;
+use std::rc::Rc;
 use chunk::*;
+use img_buffer::ImgBuffer;
+use exclusion::Exclusion;
+use list::List;
+use table::Table;
 
 pub struct Typer {
 	block: FormatBlock,
+	images: HashMap<String, Rc<ImgBuffer>>,
 }
 
 impl Typer {
@@ -13,10 +20,17 @@ impl Typer {
 	pub fn new() -> Self {
 		Self {
 			block: FormatBlock::new(),
+			images: HashMap::new(),
 		}
 	}
 
 
+	// images for fill="pattern(name)" and <img src="name">
+	pub fn add_image(&mut self, name: &str, image: ImgBuffer) {
+		self.images.insert(name.to_string(), Rc::new(image));
+	}
+
+
 	pub fn parse(&mut self, xml_string: &str) -> Vec<FormatBlock> {
 
 		let file = BufReader::new(xml_string.as_bytes());
@@ -24,6 +38,10 @@ impl Typer {
 		
 		let mut blocks: Vec<FormatBlock> = Vec::new();
 		let mut level: usize = 0;
+		let mut containers: Vec<FormatBlock> = Vec::new();
+		let mut containers_count: usize = 0;
+		// open lists with the level of their chunk
+		let mut lists: Vec<(List, usize)> = Vec::new();
 
 		fn get_chunk<'a>(chunk: &'a mut FormatChunk, level:usize) -> Option<&'a mut FormatChunk> {
 			if level == 0 {
@@ -41,50 +59,244 @@ impl Typer {
 			None
 		}
 
+		// the last cell of a table, or the last block
+		fn get_block(blocks: &mut [FormatBlock]) -> Option<&mut FormatBlock> {
+			let block = blocks.last_mut()?;
+			if block.table.as_ref().is_some_and(|table| table.cells().next().is_some()) {
+				return block.table.as_mut().and_then(|table| table.get_last_cell());
+			}
+			Some(block)
+		}
+
 		for e in parser {
 			match e {
 				Ok( XmlEvent::StartElement { name, attributes, .. } ) => {
 					match &name.local_name[..] {
+						"blocks" => {
+							containers_count += 1;
+							let mut container = containers
+								.last()
+								.unwrap_or(&self.block)
+								.new_empty();
+							for attribute in attributes {
+								container.set_attribute(&attribute.name.local_name, &attribute.value);
+							}
+							container.flow = true;
+							container.flow_container = containers_count;
+							containers.push(container);
+						}
 						"block" => {
 							level = 0;
-							let mut block = self.block.new_empty();
+							lists.clear();
+							let mut block = containers
+								.last()
+								.unwrap_or(&self.block)
+								.new_empty();
 							for attribute in attributes {
+								if attribute.name.local_name == "y" {
+									block.flow = false;
+								}
 								block.set_attribute(&attribute.name.local_name, &attribute.value);
 							}
 							blocks.push(block);
 						}
-						"s" => {
+						"table" => {
+							level = 0;
+							lists.clear();
+							let mut block = containers
+								.last()
+								.unwrap_or(&self.block)
+								.new_empty();
+							for attribute in attributes {
+								if attribute.name.local_name == "y" {
+									block.flow = false;
+								}
+								block.set_attribute(&attribute.name.local_name, &attribute.value);
+							}
+							block.table = Some(Table::new());
+							blocks.push(block);
+						}
+						"tr" => {
+							let table = blocks
+								.last_mut()
+								.and_then(|block| block.table.as_mut())
+								.expect("<tr> must be in <table>");
+							table.rows.push(Vec::new());
+						}
+						"td" => {
+							level = 0;
+							lists.clear();
 							let block = blocks
 								.last_mut()
+								.expect("<td> must be in <table>");
+							let mut cell = block.new_empty();
+							cell.width = 0.0;
+							cell.height = 0.0;
+							cell.x = 0.0;
+							cell.y = 0.0;
+							cell.margin_top = 0.0;
+							cell.margin_bottom = 0.0;
+							cell.flow = false;
+							for attribute in attributes {
+								cell.set_attribute(&attribute.name.local_name, &attribute.value);
+							}
+
+							let table = block.table
+								.as_mut()
+								.expect("<td> must be in <table>");
+							if table.rows.is_empty() {
+								table.rows.push(Vec::new());
+							}
+							table.rows.last_mut().unwrap().push(cell);
+						}
+						"exclusion" => {
+							let block = get_block(&mut blocks)
+								.expect("uou mast create <block> for <exclusion>");
+							let mut exclusion = Exclusion::new();
+							for attribute in attributes {
+								exclusion.set_attribute(&attribute.name.local_name, &attribute.value);
+							}
+							block.exclusions.push(exclusion);
+						}
+						"p" => {
+							let block = get_block(&mut blocks)
+								.expect("uou mast create <block> for <p>");
+
+							let chunk = get_chunk(&mut block.chunk, level)
+								.unwrap();
+							let mut new_chunk = chunk.new_empty();
+							let mut paragraph = Paragraph::new();
+							for attribute in attributes {
+								if Paragraph::has_attribute(&attribute.name.local_name) {
+									paragraph.set_attribute(&attribute.name.local_name, &attribute.value);
+								} else {
+									set_chunk_attribute(&self.images, &mut new_chunk, &attribute.name.local_name, &attribute.value);
+								}
+							}
+							new_chunk.paragraph = Some(block.paragraphs.len());
+							block.paragraphs.push(paragraph);
+							chunk.chunks.push(FormatChunks::Chunk(Box::new(new_chunk)));
+							level += 1;
+						}
+						"ul"|"ol" => {
+							let block = get_block(&mut blocks)
+								.expect("uou mast create <block> for <ul> or <ol>");
+
+							let mut list = List::new(name.local_name == "ol", lists.len());
+							list.margin_left = lists
+								.last()
+								.map_or(0.0, |(list, _)| list.margin_left + list.indent);
+
+							let chunk = get_chunk(&mut block.chunk, level)
+								.unwrap();
+							let mut new_chunk = chunk.new_empty();
+							for attribute in attributes {
+								if List::has_attribute(&attribute.name.local_name) {
+									list.set_attribute(&attribute.name.local_name, &attribute.value);
+								} else if Paragraph::has_attribute(&attribute.name.local_name) {
+									list.paragraph.set_attribute(&attribute.name.local_name, &attribute.value);
+								} else {
+									set_chunk_attribute(&self.images, &mut new_chunk, &attribute.name.local_name, &attribute.value);
+								}
+							}
+							chunk.chunks.push(FormatChunks::Chunk(Box::new(new_chunk)));
+							level += 1;
+							lists.push((list, level));
+						}
+						"li" => {
+							let block = get_block(&mut blocks)
+								.expect("uou mast create <block> for <li>");
+							let (list, _) = lists
+								.last_mut()
+								.expect("<li> must be in <ul> or <ol>");
+
+							let chunk = get_chunk(&mut block.chunk, level)
+								.unwrap();
+							let mut new_chunk = chunk.new_empty();
+							let mut paragraph = list.next_item();
+							for attribute in attributes {
+								if Paragraph::has_attribute(&attribute.name.local_name) {
+									paragraph.set_attribute(&attribute.name.local_name, &attribute.value);
+								} else {
+									set_chunk_attribute(&self.images, &mut new_chunk, &attribute.name.local_name, &attribute.value);
+								}
+							}
+							new_chunk.paragraph = Some(block.paragraphs.len());
+							block.paragraphs.push(paragraph);
+							chunk.chunks.push(FormatChunks::Chunk(Box::new(new_chunk)));
+							level += 1;
+						}
+						"s" => {
+							let block = get_block(&mut blocks)
 								.expect("uou mast create <block> for <s>");
 							
 							let chunk = get_chunk(&mut block.chunk, level)
 								.unwrap();
 							let mut new_chunk = chunk.new_empty();
 							for attribute in attributes {
-								new_chunk.set_attribute(&attribute.name.local_name, &attribute.value);
+								set_chunk_attribute(&self.images, &mut new_chunk, &attribute.name.local_name, &attribute.value);
 							}
-							chunk.chunks.push(FormatChunks::Chunk(new_chunk));
+							chunk.chunks.push(FormatChunks::Chunk(Box::new(new_chunk)));
 							level += 1;
 						}
+						"img" => {
+							let block = get_block(&mut blocks)
+								.expect("uou mast create <block> for <img>");
+
+							let src = attributes
+								.iter()
+								.find(|e| e.name.local_name == "src")
+								.map(|e| e.value.clone())
+								.unwrap_or_default();
+							let image = match self.images.get(&src) {
+								Some(image) => image.clone(),
+								None => {
+									println!("unknown image '{}' for attribute: 'src'", src);
+									continue;
+								}
+							};
+
+							let chunk = get_chunk(&mut block.chunk, level)
+								.unwrap();
+							let mut new_chunk = chunk.new_empty();
+							let mut image = InlineImage::new(image);
+							for attribute in attributes {
+								if InlineImage::has_attribute(&attribute.name.local_name) {
+									image.set_attribute(&attribute.name.local_name, &attribute.value);
+								} else {
+									set_chunk_attribute(&self.images, &mut new_chunk, &attribute.name.local_name, &attribute.value);
+								}
+							}
+							new_chunk.image = Some(image);
+							new_chunk.chunks.push(FormatChunks::String(OBJECT_REPLACEMENT.to_string()));
+							chunk.chunks.push(FormatChunks::Chunk(Box::new(new_chunk)));
+						}
 						_=>{}
 					}
 				}
 				Ok( XmlEvent::EndElement{name, ..} ) => {
 					match &name.local_name[..] {
-						"block" => {
+						"blocks" => {
+							containers.pop();
+						}
+						"block"|"table"|"td" => {
 							level = 0;
 						}
-						"s" => {
+						"s"|"p"|"li" => {
+							level -= 1;
+						}
+						"ul"|"ol" => {
+							lists.pop();
 							level -= 1;
 						}
 						_=>{}
 					}
 				}
 				Ok( XmlEvent::Characters(str_chunks) ) | Ok( XmlEvent::Whitespace(str_chunks) ) => {
-					if level > 0 {
-						let block = blocks
-							.last_mut()
+					// text between <li> elements is ignored
+					let is_in_list = lists.last().is_some_and(|(_, list_level)| *list_level == level);
+					if level > 0 && !is_in_list {
+						let block = get_block(&mut blocks)
 							.expect("text must by in <s>");
 
 						let chunk = get_chunk(&mut block.chunk, level).unwrap();
@@ -102,3 +314,17 @@ impl Typer {
 		blocks
 	}
 }
+
+
+// pattern(name) is an image added to the typer
+fn set_chunk_attribute(images: &HashMap<String, Rc<ImgBuffer>>, chunk: &mut FormatChunk, key: &str, val: &str) {
+	if key == "fill" && val.starts_with("pattern(") && val.ends_with(')') {
+		let name = val["pattern(".len()..val.len()-1].trim();
+		match images.get(name) {
+			Some(image) => { chunk.fill = Some(Paint::Pattern(image.clone())) }
+			None => { println!("unknown image '{}' for attribute: '{}'", name, key); }
+		}
+		return;
+	}
+	chunk.set_attribute(key, val);
+}
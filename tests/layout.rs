@@ -0,0 +1,641 @@
+extern crate typer;
+
+use std::path::PathBuf;
+use typer::rusttype::Font;
+use typer::{FontTables, FormatChunk, ImgBuffer, TextAlignHorizontal, TextRenderer, Typer};
+
+
+fn load_fonts<'a>() -> Vec<(String, Font<'a>, FontTables)> {
+	TextRenderer::load_fonts(vec![("roboto".to_string(), PathBuf::from("fonts/Roboto-Regular.ttf"))])
+}
+
+// typer with a 16x16 red "icon" image
+fn typer_with_icon() -> Typer {
+	let mut typer = Typer::new();
+	typer.add_image("icon", ImgBuffer::new(16, 16, &[255, 0, 0, 255]));
+	typer
+}
+
+// the rightmost column with ink between the rows, on the white background
+fn ink_right(buffer: &ImgBuffer, top: f32, bottom: f32) -> Option<usize> {
+	(top.max(0.0) as usize..(bottom as usize).min(buffer.height))
+		.flat_map(|y| (0..buffer.width).filter(move |x| buffer.buffer[(y * buffer.width + x) * 4] < 128))
+		.max()
+}
+
+
+#[test]
+fn fit_shrink_with_baseline_shift() {
+	let fonts = load_fonts();
+	let blocks = Typer::new().parse(r#"<block width="200" height="30" fit="shrink">
+		<s font-size="40">Hello <s baseline-shift="super">2</s> world</s>
+	</block>"#);
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+
+	let (block, render_block) = &layout.blocks[0];
+	assert!(render_block.height <= block.height, "{} > {}", render_block.height, block.height);
+}
+
+#[test]
+fn fit_shrink_with_letter_spacing() {
+	let fonts = load_fonts();
+	let blocks = Typer::new().parse(r#"<block width="200" height="30" fit="shrink">
+		<s font-size="40" letter-spacing="10px">Hello <s baseline-shift="4px">2</s> world</s>
+	</block>"#);
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+
+	let (block, render_block) = &layout.blocks[0];
+	assert!(render_block.height <= block.height, "{} > {}", render_block.height, block.height);
+	assert!(render_block.width <= block.width, "{} > {}", render_block.width, block.width);
+}
+
+#[test]
+fn fit_shrink_long_word() {
+	let fonts = load_fonts();
+	let blocks = Typer::new().parse(r#"<block width="60" fit="shrink"><s font-size="40">water</s></block>"#);
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+
+	let (block, render_block) = &layout.blocks[0];
+	assert_eq!(render_block.lines.len(), 1);
+	assert!(render_block.width <= block.width, "{} > {}", render_block.width, block.width);
+}
+
+#[test]
+fn long_word_without_fit() {
+	let fonts = load_fonts();
+	let blocks = Typer::new().parse(r#"<block width="60"><s font-size="40">water</s></block>"#);
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+
+	let lines = &layout.blocks[0].1.lines;
+	assert!(lines.len() > 1);
+	assert!(lines[0].split_word);
+}
+
+#[test]
+fn justify_across_frames() {
+	let fonts = load_fonts();
+	let blocks = Typer::new().parse(r#"<blocks>
+		<block id="a" next="b" x="10" y="10" width="150" height="50" text-align="justify">
+			<s font-size="20">The quick brown fox jumps over the lazy dog and keeps running far away into the woods until night.</s>
+		</block>
+		<block id="b" x="200" y="10" width="150" text-align="justify"></block>
+	</blocks>"#);
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+	let mut buffer = ImgBuffer::new(400, 200, &[255, 255, 255, 255]);
+	TextRenderer::render(&layout, &mut buffer);
+
+	// the last line of the first frame continues in the next one
+	let (block, render_block) = &layout.blocks[0];
+	let line = render_block.lines.last().unwrap();
+	assert!(!line.force_break);
+	assert!(line.width < line.max_width - 5.0);
+	let right = ink_right(&buffer, block.y + line.y, block.y + line.y + line.height).unwrap();
+	assert!(right as f32 >= block.x + block.width - 3.0, "{}", right);
+
+	let (block, render_block) = &layout.blocks[1];
+	let line = render_block.lines.last().unwrap();
+	assert!(line.force_break);
+	let right = ink_right(&buffer, block.y + line.y, block.y + line.y + line.height).unwrap();
+	assert!((right as f32) < block.x + line.width + 3.0, "{}", right);
+}
+
+#[test]
+fn justify_across_pages() {
+	let fonts = load_fonts();
+	let blocks = Typer::new().parse(r#"<blocks>
+		<block text-align="justify" orphans="1" widows="1">
+			<s font-size="20">The quick brown fox jumps over the lazy dog and keeps running far away into the woods until night.</s>
+		</block>
+	</blocks>"#);
+	let pages = TextRenderer::paginate(blocks, 1.0, &fonts, (170.0, 60.0), (5.0, 10.0, 5.0, 10.0));
+	assert!(pages.len() > 1);
+
+	let mut buffer = ImgBuffer::new(170, 60, &[255, 255, 255, 255]);
+	TextRenderer::render(&pages[0], &mut buffer);
+
+	let (block, render_block) = pages[0].blocks.last().unwrap();
+	let line = render_block.lines.last().unwrap();
+	assert!(!line.force_break);
+	assert!(line.width < line.max_width - 5.0);
+	let right = ink_right(&buffer, block.y + line.y, block.y + line.y + line.height).unwrap();
+	assert!(right as f32 >= block.x + line.max_width - 3.0, "{}", right);
+}
+
+#[test]
+fn exclusion_skips_narrow_parts() {
+	let fonts = load_fonts();
+	let blocks = Typer::new().parse(r#"<block width="200">
+		<exclusion x="0" y="0" width="170" height="40"/>
+		<s font-size="20">water flows down the hill</s>
+	</block>"#);
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+
+	// the 30px on the right of the exclusion are narrower than "water"
+	let lines = &layout.blocks[0].1.lines;
+	let text_lines: Vec<_> = lines.iter().filter(|line| !line.glyphs.is_empty()).collect();
+	assert_eq!(text_lines.len(), 1);
+	assert!(text_lines[0].y >= 40.0);
+	assert!(lines.iter().all(|line| !line.split_word));
+}
+
+#[test]
+fn exclusion_wraps_around() {
+	let fonts = load_fonts();
+	let blocks = Typer::new().parse(r#"<block width="200">
+		<exclusion x="0" y="0" width="100" height="40"/>
+		<s font-size="20">water flows down the hill</s>
+	</block>"#);
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+
+	let line = &layout.blocks[0].1.lines[0];
+	assert_eq!(line.x, 100.0);
+	assert!(!line.glyphs.is_empty());
+}
+
+#[test]
+fn stroke_at_buffer_edge() {
+	let fonts = load_fonts();
+	let blocks = Typer::new().parse(r##"<block x="185" y="10" width="100">
+		<s font-size="40" stroke-width="6" stroke-color="#000000">W</s>
+	</block>"##);
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+	let mut buffer = ImgBuffer::new(200, 80, &[255, 255, 255, 255]);
+	TextRenderer::render(&layout, &mut buffer);
+
+	// the glyph doesn't fit the buffer, so neither the fill nor the stroke are drawn,
+	// pixels past the right edge would wrap to the next row
+	assert_eq!(ink_right(&buffer, 0.0, 80.0), None);
+}
+
+#[test]
+fn optimal_breaking_without_width() {
+	let fonts = load_fonts();
+	let blocks = Typer::new().parse(r#"<block line-break="optimal">
+		<s font-size="20">Hello world, this text has no width to wrap in</s>
+	</block>"#);
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+
+	assert_eq!(layout.blocks[0].1.lines.len(), 1);
+}
+
+#[test]
+fn optimal_breaking_in_table() {
+	let fonts = load_fonts();
+	let table = |line_break: &str| format!(r#"<table x="0" y="0" width="600" line-break="{}"><tr>
+		<td><s font-size="20">The first column with a longer text</s></td>
+		<td><s font-size="20">Second</s></td>
+	</tr></table>"#, line_break);
+
+	let cell_x = |xml: &str| {
+		let layout = TextRenderer::format(Typer::new().parse(xml), 1.0, &fonts);
+		let table = layout.blocks[0].0.table.as_ref().unwrap();
+		let x = table.cells().nth(1).unwrap().x;
+		x
+	};
+	assert_eq!(cell_x(&table("greedy")), cell_x(&table("optimal")));
+}
+
+#[test]
+fn exclusion_without_width() {
+	let fonts = load_fonts();
+	let blocks = Typer::new().parse(r#"<block>
+		<exclusion x="0" y="0" width="50" height="20"/>
+		<s>no width block</s>
+	</block>"#);
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+
+	let lines = &layout.blocks[0].1.lines;
+	assert_eq!(lines.len(), 1);
+	assert_eq!(lines[0].glyphs.len(), 14);
+}
+
+#[test]
+fn exclusion_touching_the_line() {
+	let fonts = load_fonts();
+	let blocks = Typer::new().parse(r#"<block width="200">
+		<s font-size="20">first line of text, second line</s>
+		<exclusion x="0" y="-20" width="100" height="20"/>
+	</block>"#);
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+
+	// the exclusion ends at the top of the first line
+	assert_eq!(layout.blocks[0].1.lines[0].x, 0.0);
+}
+
+#[test]
+fn ellipsis_after_image() {
+	let fonts = load_fonts();
+	let blocks = typer_with_icon().parse(r#"<block width="150" max-lines="1" overflow="ellipsis">
+		<s font-size="20">Some <img src="icon" width="30"/> extraordinarily</s>
+	</block>"#);
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+
+	let line = &layout.blocks[0].1.lines[0];
+	let (_, chunk, symbol, _) = line.glyphs.last().unwrap();
+	assert_eq!(*symbol, '…');
+	assert!(chunk.image.is_none());
+	assert!(line.glyphs.iter().any(|(_, chunk, ..)| chunk.image.is_some()));
+}
+
+// one word on every line, 4 lines fit the page
+fn paginate_words(words: usize, orphans: usize, widows: usize) -> Vec<usize> {
+	let fonts = load_fonts();
+	let text = vec!["word"; words].join(" ");
+	let blocks = Typer::new().parse(&format!(r#"<blocks>
+		<block width="40" orphans="{}" widows="{}"><s font-size="20">{}</s></block>
+	</blocks>"#, orphans, widows, text));
+	TextRenderer::paginate(blocks, 1.0, &fonts, (100.0, 70.0), (0.0, 0.0, 0.0, 0.0))
+		.iter()
+		.map(|page| page.blocks.iter().map(|(_, render_block)| render_block.lines.len()).sum())
+		.collect()
+}
+
+#[test]
+fn widows_and_orphans() {
+	assert_eq!(paginate_words(6, 2, 2), vec![4, 2]);
+	assert_eq!(paginate_words(6, 2, 3), vec![3, 3]);
+	assert_eq!(paginate_words(5, 1, 1), vec![4, 1]);
+}
+
+#[test]
+fn widows_and_orphans_on_empty_page() {
+	// 3 widows leave 2 lines, less than 3 orphans, then the lines that fit are used
+	assert_eq!(paginate_words(5, 3, 3), vec![4, 1]);
+}
+
+#[test]
+fn linked_frames_cycle() {
+	let fonts = load_fonts();
+	let blocks = Typer::new().parse(r#"<blocks>
+		<block id="a" next="b" x="0" y="0" width="150" height="20"><s font-size="20">The text of the first frame goes on into the second one</s></block>
+		<block id="b" next="a" x="200" y="0" width="150"></block>
+	</blocks>"#);
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+
+	// the chain starts at the first block, the text is not lost
+	let lines = |i: usize| layout.blocks[i].1.lines.iter().filter(|line| !line.glyphs.is_empty()).count();
+	assert_eq!(lines(0), 1);
+	assert!(lines(1) > 1);
+}
+
+#[test]
+fn linked_frames_chain() {
+	let fonts = load_fonts();
+	let blocks = Typer::new().parse(r#"<blocks>
+		<block id="b" x="200" y="0" width="150"><s>ignored</s></block>
+		<block id="a" next="b" x="0" y="0" width="150" height="20"><s font-size="20">The text of the first frame goes on into the second one</s></block>
+	</blocks>"#);
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+
+	let text: String = layout.blocks[0].1.lines.iter().flat_map(|line| line.glyphs.iter().map(|g| g.2)).collect();
+	assert!(text.ends_with("second one"), "{}", text);
+	assert!(!text.contains("ignored"));
+}
+
+#[test]
+fn ligatures() {
+	let fonts = load_fonts();
+	let text = |features: &str| {
+		let blocks = Typer::new().parse(&format!(r#"<block><s font-features="{}">office</s></block>"#, features));
+		let layout = TextRenderer::format(blocks, 1.0, &fonts);
+		let glyphs = &layout.blocks[0].1.lines[0].glyphs;
+		let text: String = glyphs.iter().map(|g| g.1.cluster.clone().unwrap_or_else(|| g.2.to_string())).collect();
+		(glyphs.len(), text)
+	};
+
+	let (count, default) = text("");
+	assert!(count < 6, "no ligature in {}", default);
+	assert_eq!(default, "office");
+	assert_eq!(text("-liga"), (6, "office".to_string()));
+}
+
+#[test]
+fn baseline_shift_attribute_order() {
+	let fonts = load_fonts();
+	let shift = |attributes: &str| {
+		let blocks = Typer::new().parse(&format!(r#"<block><s font-size="30">x<s {}>2</s></s></block>"#, attributes));
+		let layout = TextRenderer::format(blocks, 1.0, &fonts);
+		let (glyph, chunk, _, _) = layout.blocks[0].1.lines[0].glyphs.last().unwrap();
+		(glyph.scale().y, chunk.baseline_shift)
+	};
+
+	let (size, super_shift) = shift(r#"font-size="20" baseline-shift="super""#);
+	assert_eq!(size, 20.0);
+	assert!(super_shift > 0.0);
+	assert_eq!(shift(r#"baseline-shift="super" font-size="20""#), (size, super_shift));
+	assert_eq!(shift(r#"baseline-shift="0.5em" font-size="20""#), shift(r#"font-size="20" baseline-shift="0.5em""#));
+	assert_eq!(shift(r#"baseline-shift="0.5em" font-size="20""#).1, 10.0);
+
+	// without a font size super makes the text smaller
+	assert_eq!(shift(r#"baseline-shift="super""#).0, 21.0);
+}
+
+#[test]
+fn nested_opacity() {
+	let fonts = load_fonts();
+	let blocks = Typer::new().parse(r#"<block>
+		<s opacity="0.5">a<s opacity="0.5">b</s><s>c</s></s>
+	</block>"#);
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+
+	let opacity: Vec<f32> = layout.blocks[0].1.lines[0].glyphs.iter().map(|g| g.1.opacity).collect();
+	assert_eq!(opacity, vec![0.5, 0.25, 0.5]);
+
+	// setting it again replaces the value
+	let mut chunk = FormatChunk::new();
+	chunk.set_attribute("opacity", "0.8");
+	chunk.set_attribute("opacity", "0.5");
+	assert_eq!(chunk.new_empty().get_render_chunk().opacity, 0.5);
+}
+
+#[test]
+fn columns_balanced() {
+	let fonts = load_fonts();
+	let text = "word ".repeat(40);
+	let column_lines = |attributes: &str| {
+		let blocks = Typer::new().parse(&format!(r#"<block width="300" columns="2" column-gap="20" {}><s font-size="20">{}</s></block>"#, attributes, text));
+		let layout = TextRenderer::format(blocks, 1.0, &fonts);
+		let lines = &layout.blocks[0].1.lines;
+		assert!(lines.iter().all(|line| line.x + line.width <= 140.0 || line.x >= 160.0));
+		let first = lines.iter().filter(|line| line.x < 140.0).count();
+		(first, lines.len() - first)
+	};
+
+	let (first, second) = column_lines("");
+	assert!(second > 0 && first - second <= 1, "{} {}", first, second);
+
+	// a fixed height fills the first column, balance splits the lines
+	let (first, second) = column_lines(r#"height="200""#);
+	assert!(first > second + 1, "{} {}", first, second);
+	let (first, second) = column_lines(r#"height="200" column-fill="balance""#);
+	assert!(second > 0 && first - second <= 1, "{} {}", first, second);
+}
+
+#[test]
+fn optimal_breaking_even_lines() {
+	let fonts = load_fonts();
+	let text = "Knuth and Plass break the whole paragraph at once, so a long word at the end of a line doesn't leave a large gap in it while the other lines stay tight, which greedy breaking does";
+	// the gaps at the end of the lines, without the last line
+	let gaps = |line_break: &str| -> Vec<f32> {
+		let blocks = Typer::new().parse(&format!(r#"<block width="280" line-break="{}"><s font-size="16">{}</s></block>"#, line_break, text));
+		let layout = TextRenderer::format(blocks, 1.0, &fonts);
+		let lines = &layout.blocks[0].1.lines;
+		assert!(lines.iter().all(|line| line.width <= 280.0));
+		lines[..lines.len()-1].iter().map(|line| 280.0 - line.width).collect()
+	};
+	let max = |gaps: &[f32]| gaps.iter().cloned().fold(0.0, f32::max);
+
+	let greedy = gaps("greedy");
+	let optimal = gaps("optimal");
+	assert_eq!(greedy.len(), optimal.len());
+	assert!(max(&optimal) < max(&greedy), "{:?} {:?}", optimal, greedy);
+}
+
+#[test]
+fn paragraphs() {
+	let fonts = load_fonts();
+	let blocks = Typer::new().parse(r#"<block width="200"><s font-size="20"><p text-indent="30" paragraph-spacing="15">The first paragraph is long enough for two lines</p><p text-align="right" paragraph-spacing-before="25">Second one</p></s></block>"#);
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+
+	let lines = &layout.blocks[0].1.lines;
+	assert_eq!(lines.len(), 3);
+	// the indent is only on the first line
+	assert_eq!(lines[0].x, 30.0);
+	assert_eq!(lines[1].x, 0.0);
+	// the spacing between the paragraphs collapses to the larger one, in the height of the line
+	assert_eq!(lines[0].height, lines[1].height);
+	assert!((lines[2].height - lines[1].height - 25.0).abs() < 0.01);
+	assert_eq!(lines[2].y, lines[1].y + lines[1].height);
+	assert_eq!(lines[2].text_align, Some(TextAlignHorizontal::Right));
+}
+
+#[test]
+fn lists() {
+	let fonts = load_fonts();
+	let blocks = Typer::new().parse(r#"<block width="200"><s font-size="16"><ol start="3" list-style="upper-roman"><li>Third</li><li>The fourth item wraps to the next line<ul><li>Nested</li><li>Nested</li></ul></li></ol></s></block>"#);
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+
+	let lines: Vec<(f32, String)> = layout.blocks[0].1.lines
+		.iter()
+		.map(|line| (line.x, line.marker.iter().map(|g| g.2).collect::<String>().trim().to_string()))
+		.collect();
+	assert_eq!(lines, vec![
+		(24.0, "III.".to_string()),
+		(24.0, "IV.".to_string()),
+		// the lines after the first one have no marker and the same indent
+		(24.0, "".to_string()),
+		(48.0, "•".to_string()),
+		(48.0, "•".to_string()),
+	]);
+}
+
+#[test]
+fn table_columns() {
+	let fonts = load_fonts();
+	let blocks = Typer::new().parse(r#"<table x="0" y="0" width="400"><tr>
+		<td width="100"><s font-size="16">Fixed</s></td>
+		<td vertical-align="bottom"><s font-size="16">Short</s></td>
+		<td><s font-size="16">A much longer text that has to wrap in the column</s></td>
+	</tr><tr>
+		<td><s font-size="16">a</s></td><td><s font-size="16">b</s></td><td><s font-size="16">c</s></td>
+	</tr></table>"#);
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+
+	let (block, render_block) = &layout.blocks[0];
+	let cells: Vec<_> = block.table.as_ref().unwrap().cells().collect();
+	assert_eq!(render_block.width, 400.0);
+	// the fixed column keeps its width, the others share the rest by their content
+	assert_eq!(cells[0].width, 100.0);
+	assert_eq!(cells[1].x, 100.0);
+	assert!((cells[0].width + cells[1].width + cells[2].width - 400.0).abs() < 0.01);
+	assert!(cells[2].width > cells[1].width * 2.0);
+	assert!(render_block.cells[2].lines.len() > 1);
+	// a column is never narrower than its longest word
+	assert_eq!(render_block.cells[1].lines.len(), 1);
+	// the cells of a row have the height of the highest one
+	assert!(cells[..3].iter().all(|cell| cell.height == cells[2].height));
+	assert_eq!(cells[3].y, cells[2].y + cells[2].height);
+	assert_eq!(cells[4].x, cells[1].x);
+	// aligned to the bottom of the row
+	assert_eq!(cells[0].get_vertical_offset(&render_block.cells[0]), 0.0);
+	let offset = cells[1].get_vertical_offset(&render_block.cells[1]);
+	assert_eq!(offset + render_block.cells[1].height, cells[1].height);
+	assert!(offset > 0.0);
+}
+
+#[test]
+fn tab_stops() {
+	let fonts = load_fonts();
+	let blocks = Typer::new().parse(r#"<block width="400" tab-stops="200:right:. 300:decimal 350"><s font-size="16">Name&#9;Price&#9;12.50&#9;x</s></block>"#);
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+
+	// the start of every glyph
+	let line = &layout.blocks[0].1.lines[0];
+	let positions: Vec<(char, f32)> = line.glyphs
+		.iter()
+		.scan(line.x, |x, g| {
+			*x += g.3;
+			Some((g.2, *x - g.3))
+		})
+		.collect();
+	let find = |symbol: char, after: f32| positions.iter().find(|(s, x)| *s == symbol && *x >= after).unwrap().1;
+	let near = |a: f32, b: f32| (a - b).abs() < 0.01;
+
+	// right: the text ends at the stop, with the leader before it
+	let price = find('P', 0.0);
+	assert!(near(find('\t', price), 200.0));
+	assert!(positions.iter().filter(|(s, x)| *s == '.' && *x < price).count() > 10);
+	// decimal: the separator is at the stop
+	assert!(near(find('.', price), 300.0));
+	// left: the text starts at the stop
+	assert!(near(find('x', 0.0), 350.0));
+}
+
+// rows with ink across the whole text, the text has gaps between the glyphs
+fn solid_rows(decoration: &str) -> (Vec<usize>, f32) {
+	let fonts = load_fonts();
+	let blocks = Typer::new().parse(&format!(r#"<block x="10" y="10" width="200"><s font-size="30" text-decoration="{}">nnnn</s></block>"#, decoration));
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+	let mut buffer = ImgBuffer::new(120, 60, &[255, 255, 255, 255]);
+	TextRenderer::render(&layout, &mut buffer);
+
+	let line = &layout.blocks[0].1.lines[0];
+	let (left, right) = (12, (10.0 + line.width) as usize - 2);
+	let rows = (0..buffer.height)
+		.filter(|y| (left..right).all(|x| buffer.buffer[(y * buffer.width + x) * 4] < 128))
+		.collect();
+	(rows, 10.0 + layout.blocks[0].1.baselines()[0])
+}
+
+#[test]
+fn decorations() {
+	let (rows, _) = solid_rows("none");
+	assert!(rows.is_empty());
+
+	let (underline, baseline) = solid_rows("underline");
+	let (line_through, _) = solid_rows("line-through");
+	let (overline, _) = solid_rows("overline");
+	assert!(!underline.is_empty() && !line_through.is_empty() && !overline.is_empty());
+	assert!(underline.iter().all(|y| *y as f32 > baseline));
+	// between the baseline and the top of the x-height
+	assert!(line_through.iter().all(|y| (*y as f32) < baseline && *y as f32 > baseline - 15.0));
+	assert!(overline.iter().all(|y| (*y as f32) < baseline - 15.0));
+}
+
+#[test]
+fn text_transform() {
+	let fonts = load_fonts();
+	let text = |xml: &str| {
+		let layout = TextRenderer::format(Typer::new().parse(xml), 1.0, &fonts);
+		layout.blocks[0].1.lines.iter().flat_map(|line| line.glyphs.iter().map(|g| g.2)).collect::<String>()
+	};
+
+	// the words continue across the spans
+	assert_eq!(text(r##"<block><s text-transform="capitalize">hello wo<s color="#ff0000">rld</s> <s>again</s></s></block>"##), "Hello World Again");
+	assert_eq!(text(r#"<block><s text-transform="uppercase">straße</s></block>"#), "STRASSE");
+	assert_eq!(text(r#"<block><s text-transform="uppercase" lang="tr">istanbul</s></block>"#), "İSTANBUL");
+	assert_eq!(text(r#"<block><s text-transform="lowercase">MIXED Case</s></block>"#), "mixed case");
+}
+
+#[test]
+fn text_shadows() {
+	let fonts = load_fonts();
+	let render = |shadow: &str| {
+		let blocks = Typer::new().parse(&format!(r##"<block x="10" y="10" width="100"><s font-size="40" text-shadow="{}">I</s></block>"##, shadow));
+		let layout = TextRenderer::format(blocks, 1.0, &fonts);
+		let mut buffer = ImgBuffer::new(100, 80, &[255, 255, 255, 255]);
+		TextRenderer::render(&layout, &mut buffer);
+		buffer
+	};
+
+	let right = ink_right(&render("none"), 0.0, 80.0).unwrap();
+	// the shadow is offset, without blur it is as solid as the glyph
+	let offset = ink_right(&render("15 0 0 #000000"), 0.0, 80.0).unwrap();
+	assert!((offset as i32 - right as i32 - 15).abs() <= 1, "{} {}", offset, right);
+	// the blur spreads it past the glyph
+	let blurred = render("0 0 4 #000000");
+	let is_tinted = |x: usize, y: usize| blurred.buffer[(y * blurred.width + x) * 4] < 250;
+	assert!((0..80).any(|y| is_tinted(right + 4, y)));
+	assert!(!(0..80).any(|y| is_tinted(right + 30, y)));
+}
+
+#[test]
+fn gradient_and_pattern_fills() {
+	let fonts = load_fonts();
+	// the pixels inside the glyphs, their edges are blended with the white background
+	let solid = |fill: &str| {
+		let mut typer = Typer::new();
+		typer.add_image("green", ImgBuffer::new(4, 4, &[0, 255, 0, 255]));
+		let blocks = typer.parse(&format!(r##"<block x="0" y="0" width="300"><s font-size="40" fill="{}" fill-area="span">HHHHHH</s></block>"##, fill));
+		let layout = TextRenderer::format(blocks, 1.0, &fonts);
+		let mut buffer = ImgBuffer::new(200, 60, &[255, 255, 255, 255]);
+		TextRenderer::render(&layout, &mut buffer);
+		buffer.buffer
+			.chunks(4)
+			.enumerate()
+			.map(|(i, p)| (i % buffer.width, [p[0], p[1], p[2]]))
+			.filter(|(_, p)| p.iter().map(|c| *c as u32).sum::<u32>() < 300)
+			.collect::<Vec<_>>()
+	};
+
+	let gradient = solid("linear-gradient(90, #ff0000, #0000ff)");
+	let (left, right) = (gradient.iter().min_by_key(|p| p.0).unwrap().1, gradient.iter().max_by_key(|p| p.0).unwrap().1);
+	assert!(left[0] > 200 && left[2] < 50, "{:?}", left);
+	assert!(right[2] > 200 && right[0] < 50, "{:?}", right);
+
+	let pattern = solid("pattern(green)");
+	assert!(!pattern.is_empty());
+	assert!(pattern.iter().all(|(_, p)| p[1] > 200 && p[0] < 50 && p[2] < 50));
+}
+
+#[test]
+fn blend_modes() {
+	let fonts = load_fonts();
+	// the most common color of the glyphs over the blue background, inside the glyphs
+	let color = |attributes: &str| {
+		let blocks = Typer::new().parse(&format!(r##"<block x="0" y="0" width="300"><s font-size="40" color="#ff0000" {}>HHH</s></block>"##, attributes));
+		let layout = TextRenderer::format(blocks, 1.0, &fonts);
+		let mut buffer = ImgBuffer::new(100, 60, &[0, 0, 255, 255]);
+		TextRenderer::render(&layout, &mut buffer);
+
+		let mut counts = std::collections::HashMap::new();
+		for pixel in buffer.buffer.chunks(4).filter(|p| *p != [0, 0, 255, 255]) {
+			*counts.entry([pixel[0], pixel[1], pixel[2]]).or_insert(0) += 1;
+		}
+		counts.into_iter().max_by_key(|(_, count)| *count).unwrap().0
+	};
+
+	assert_eq!(color(""), [255, 0, 0]);
+	assert_eq!(color(r#"blend-mode="multiply""#), [0, 0, 0]);
+	assert_eq!(color(r#"blend-mode="screen""#), [255, 0, 255]);
+	let half = color(r#"opacity="0.5""#);
+	assert!(half[0].abs_diff(128) <= 1 && half[1] == 0 && half[2].abs_diff(128) <= 1, "{:?}", half);
+}
+
+#[test]
+fn inline_images() {
+	let fonts = load_fonts();
+	let blocks = typer_with_icon().parse(r#"<block x="0" y="0" width="300"><s font-size="16">a <img src="icon" width="40"/> b</s></block>"#);
+	let layout = TextRenderer::format(blocks, 1.0, &fonts);
+
+	// the height keeps the aspect ratio, the line is as high as the image
+	let line = &layout.blocks[0].1.lines[0];
+	let (_, chunk, _, width) = line.glyphs.iter().find(|(_, chunk, ..)| chunk.image.is_some()).unwrap();
+	assert_eq!(*width, 40.0);
+	assert_eq!(chunk.image.as_ref().unwrap().height, 40.0);
+	assert!(line.height >= 40.0);
+
+	// drawn scaled, standing on the baseline
+	let mut buffer = ImgBuffer::new(100, 60, &[255, 255, 255, 255]);
+	TextRenderer::render(&layout, &mut buffer);
+	let red: Vec<usize> = buffer.buffer
+		.chunks(4)
+		.enumerate()
+		.filter(|(_, p)| *p == [255, 0, 0, 255])
+		.map(|(i, _)| i / buffer.width)
+		.collect();
+	assert!(red.len() >= 38 * 38, "{}", red.len());
+	let baseline = layout.blocks[0].1.baselines()[0];
+	let bottom = *red.iter().max().unwrap() as f32;
+	assert!((bottom + 1.0 - baseline).abs() <= 1.0, "{} {}", bottom, baseline);
+}